@@ -1,59 +1,86 @@
-use std::path::PathBuf;
-use log::{debug, trace};
-use openai_macros::{ai_agent, message};
-use serde_json::from_str;
-use crate::config::CONFIG;
+use log::debug;
+use crate::config::{Config, CONFIG};
 
+pub use blacklist::{blacklist, known_blacklist};
+
+pub mod blacklist;
+pub mod budget;
+pub mod cassette;
+pub mod provider;
+#[cfg(test)]
+mod scripted;
 pub mod search;
+pub mod tool;
+pub mod wire;
+
+pub const DEFAULT_MODEL: &str = "gpt-4-1106-preview";
+pub const DEFAULT_TEMPERATURE: f64 = 0.0;
+
+/// Effective settings of one agent. Each value comes from the agent's `[agents.<name>]` section,
+/// the model can also fall back to the global `model`, everything else has a built-in default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentSettings {
+    pub model: String,
+    pub temperature: f64,
+    pub max_tokens: Option<u64>,
+    pub seed: Option<u64>,
+    pub system_prompt: String,
+}
+
+impl AgentSettings {
+    pub fn load(agent: &str, default_prompt: &str) -> Self {
+        let settings = Self::resolve(&CONFIG.read().unwrap(), agent, default_prompt);
+        debug!("{agent} agent settings: {settings:?}");
+        settings
+    }
 
-fn get_root_entries() -> anyhow::Result<Vec<String>> {
-    let project_dir = CONFIG.read().unwrap().clone().project_dir.expect("no project directory found.");
-    let read_dir = std::fs::read_dir(project_dir)?;
-    
-    let mut entries = Vec::new();
-    for entry in read_dir {
-        let entry = entry?;
-        entries.push(String::from(entry.file_name().to_str().unwrap()));
+    fn resolve(config: &Config, agent: &str, default_prompt: &str) -> Self {
+        let section = config.agents.get(agent).cloned().unwrap_or_default();
+
+        Self {
+            model: section
+                .model
+                .or_else(|| config.model.clone())
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            temperature: section.temperature.unwrap_or(DEFAULT_TEMPERATURE),
+            max_tokens: section.max_tokens,
+            seed: section.seed,
+            system_prompt: section.system_prompt.unwrap_or_else(|| default_prompt.to_string()),
+        }
     }
-    
-    trace!("root entries: {entries:#?}");
-    
-    Ok(entries)
 }
 
-pub async fn blacklist() -> anyhow::Result<Vec<PathBuf>> {
-    // Get root level entries
-    let root_entries = get_root_entries()?;
-    
-    let agent = ai_agent! {
-        model: "gpt-4-1106-preview",
-        temperature: 0.0,
-        system_message: "Your job is to filter paths that contain build files from the root directory. You have to respond in a JSON array format. DO NOT FILTER OUT CONFIG OR SOURCE FILES. remember to not include anything before or after the array, your answer will have to be parsed by a computer.",
-        messages: [
-            message!(system, user: "example_input", content: r#"[
-              "app.js",
-              "dist",
-              "build",
-              "package.json",
-              "README.md",
-              "public",
-              "views",
-              "routes",
-              "models",
-              "controllers",
-              "config",
-              "tests",
-              "node_modules"
-            ]"#),
-            message!(system, user: "example_response", content: r#"["node_modules", "dist", "build"]"#),
-            message!(user, content: format!("{}", serde_json::to_string_pretty(&root_entries).unwrap())),
-        ],
-    };
-    
-    let chat = agent.create().await?;
-    let res = chat.choices[0].message.content.clone().unwrap();
-    let res = from_str(&res).map_err(|e| anyhow::Error::msg(e.to_string()))?;
-    debug!("blacklist: {res:#?}");
-    Ok(res)
-    
+#[cfg(test)]
+mod tests {
+    use crate::ai::{AgentSettings, DEFAULT_MODEL};
+    use crate::config::{AgentConfig, Config};
+
+    #[test]
+    fn agent_sections_override_the_global_model() {
+        let mut config = Config {
+            model: Some("global-model".to_string()),
+            ..Default::default()
+        };
+        config.agents.insert(
+            "blacklist".to_string(),
+            AgentConfig {
+                model: Some("cheap-model".to_string()),
+                temperature: Some(0.2),
+                seed: Some(7),
+                ..Default::default()
+            },
+        );
+
+        let blacklist = AgentSettings::resolve(&config, "blacklist", "prompt");
+        assert_eq!(blacklist.model, "cheap-model");
+        assert_eq!(blacklist.temperature, 0.2);
+        assert_eq!(blacklist.seed, Some(7));
+        assert_eq!(blacklist.system_prompt, "prompt");
+
+        let finder = AgentSettings::resolve(&config, "finder", "prompt");
+        assert_eq!(finder.model, "global-model");
+
+        let chat = AgentSettings::resolve(&Config::default(), "chat", "");
+        assert_eq!(chat.model, DEFAULT_MODEL);
+    }
 }
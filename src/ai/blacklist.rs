@@ -0,0 +1,523 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::anyhow;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::{debug, trace, warn};
+use openai_macros::{ai_agent, message};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{from_str, json, Value};
+use walkdir::WalkDir;
+use crate::as_paths;
+use crate::ai::AgentSettings;
+use crate::ai::provider::{CompletionRequest, Provider};
+use crate::ai::wire::Message;
+use crate::config::CONFIG;
+use crate::ctags::is_excluded_path;
+use crate::gitignore::GitIgnores;
+
+const BLACKLIST_PROMPT: &str = "Your job is to filter paths that contain build files from the root directory. You have to respond in a JSON array format. DO NOT FILTER OUT CONFIG OR SOURCE FILES. remember to not include anything before or after the array, your answer will have to be parsed by a computer.";
+
+/// The prompt for apis that hold the reply to the schema of [`response_format`].
+const BLACKLIST_SCHEMA_PROMPT: &str = "Your job is to filter paths that contain build files from the root directory. You have to respond with a JSON object with an `entries` array of the paths to filter out. DO NOT FILTER OUT CONFIG OR SOURCE FILES. remember to not include anything before or after the object, your answer will have to be parsed by a computer.";
+
+/// Where the answers of the blacklist agent are kept, next to the tag index.
+const CACHE_FILE: &str = ".devgpt/blacklist.json";
+
+/// Replies of the blacklist agent to try before giving up on it.
+const MAX_ATTEMPTS: usize = 3;
+
+const CORRECTION: &str = "Your answer could not be parsed. Respond with only a JSON array of the entries to filter out, like [\"dist\"], or [] for none.";
+
+const SCHEMA_CORRECTION: &str = "Your answer could not be parsed. Respond with only a JSON object with the entries to filter out, like {\"entries\": [\"dist\"]}, or {\"entries\": []} for none.";
+
+/// Directories that hold build output, dependencies or caches of common tools, with the files one
+/// of which sits next to them when they do. A directory with markers is only left out when one of
+/// them is there, a `build` directory can hold sources too.
+const BUILD_DIRS: &[(&str, &[&str])] = &[
+    ("target", &["Cargo.toml", "pom.xml"]),
+    ("node_modules", &[]),
+    ("bower_components", &[]),
+    ("dist", &["package.json", "pyproject.toml", "setup.py"]),
+    ("build", &["package.json", "build.gradle", "build.gradle.kts", "CMakeLists.txt", "pyproject.toml", "setup.py"]),
+    ("out", &["package.json"]),
+    (".venv", &[]),
+    ("venv", &["pyproject.toml", "setup.py", "requirements.txt"]),
+    ("__pycache__", &[]),
+    (".mypy_cache", &[]),
+    (".pytest_cache", &[]),
+    (".tox", &[]),
+    (".next", &[]),
+    (".nuxt", &[]),
+    (".svelte-kit", &[]),
+    (".parcel-cache", &[]),
+    (".gradle", &[]),
+    (".terraform", &[]),
+    (".cache", &["package.json"]),
+    ("coverage", &["package.json"]),
+    ("vendor", &["go.mod", "composer.json"]),
+    ("zig-cache", &["build.zig"]),
+    ("zig-out", &["build.zig"]),
+    ("_build", &["mix.exs", "rebar.config"]),
+    ("deps", &["mix.exs"]),
+];
+
+/// Directories that hold sources, docs or config in most projects, the agent isn't asked about them.
+const SOURCE_DIRS: &[&str] = &[
+    "src", "lib", "app", "apps", "packages", "crates", "cmd", "pkg", "internal", "include", "tests", "test",
+    "spec", "benches", "examples", "docs", "doc", "scripts", "config", "public", "assets", "static", "migrations",
+    ".github", ".cargo",
+];
+
+/// The `[blacklist]` section, what to leave out of the project besides the ignore files and the
+/// built-in build directories.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BlacklistConfig {
+    /// Root entries to always leave out.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Root entries to never leave out, they win over every other rule.
+    #[serde(default)]
+    pub keep: Vec<String>,
+    /// Asks the blacklist agent about directories no rule knows, defaults to true.
+    #[serde(default)]
+    pub ask_model: Option<bool>,
+}
+
+/// The paths to leave out of the project, relative to it. The rules decide about the root entries
+/// first, from the config, the ignore files and the built-in tables. The blacklist agent is only
+/// asked about directories none of them know, and its answers are cached so later runs don't ask
+/// again. Below the root, build directories next to their markers and git ignored directories are
+/// left out too.
+pub async fn blacklist(provider: &dyn Provider) -> anyhow::Result<Vec<PathBuf>> {
+    configured_blacklist(Some(provider)).await
+}
+
+/// The blacklist from the rules and the cached answers of the blacklist agent, without asking it
+/// about directories no rule knows.
+pub async fn known_blacklist() -> anyhow::Result<Vec<PathBuf>> {
+    configured_blacklist(None).await
+}
+
+async fn configured_blacklist(provider: Option<&dyn Provider>) -> anyhow::Result<Vec<PathBuf>> {
+    let (project_dir, config, json_schema) = {
+        let config = CONFIG.read().unwrap();
+        let project_dir = config.project_dir.clone().ok_or_else(|| anyhow!("no project directory configured"))?;
+        (project_dir, config.blacklist.clone(), config.provider.json_schema)
+    };
+    blacklist_of(provider, &project_dir, &config, json_schema).await
+}
+
+async fn blacklist_of(
+    provider: Option<&dyn Provider>,
+    project_dir: &Path,
+    config: &BlacklistConfig,
+    json_schema: bool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let rules = Rules::new(project_dir, config);
+    let cache_path = project_dir.join(CACHE_FILE);
+    let mut cache = read_cache(&cache_path);
+
+    let mut excluded = vec![];
+    let mut unknown = vec![];
+    for entry in get_root_entries(project_dir)? {
+        let is_dir = project_dir.join(&entry).is_dir();
+        match rules.classify(&entry, is_dir).or_else(|| cache.get(&entry).copied()) {
+            Some(true) => excluded.push(entry),
+            Some(false) => {}
+            None => unknown.push(entry),
+        }
+    }
+
+    let provider = provider.filter(|_| !unknown.is_empty() && config.ask_model.unwrap_or(true));
+    let answer = match provider {
+        // the agent only helps out with what the rules don't know, the rules still hold without it
+        Some(provider) => ask(provider, &unknown, json_schema).await.unwrap_or_else(|e| {
+            warn!("could not ask the blacklist agent, keeping the directories no rule knows: {e:#}");
+            None
+        }),
+        None => None,
+    };
+    if let Some(answer) = answer {
+        for entry in unknown {
+            let exclude = answer.contains(&entry);
+            cache.insert(entry.clone(), exclude);
+            if exclude {
+                excluded.push(entry);
+            }
+        }
+        write_cache(&cache_path, &cache)?;
+    }
+
+    let mut excluded = excluded.into_iter().map(PathBuf::from).collect::<Vec<_>>();
+    excluded.extend(nested_build_dirs(project_dir, &as_paths(&excluded)));
+    excluded.sort();
+    debug!("blacklist: {excluded:#?}");
+    Ok(excluded)
+}
+
+/// Build directories and git ignored directories below the root entries, the walk doesn't go into
+/// the ones `excluded` already leaves out.
+fn nested_build_dirs(project_dir: &Path, excluded: &[&Path]) -> Vec<PathBuf> {
+    let mut found = vec![];
+    let mut ignores = GitIgnores::new(project_dir);
+    let walker = WalkDir::new(project_dir).min_depth(1).sort_by_file_name().into_iter().filter_entry(|entry| {
+        let relative = entry.path().strip_prefix(project_dir).unwrap_or(entry.path());
+        if !entry.file_type().is_dir() || is_excluded_path(relative, excluded) {
+            return false;
+        }
+        if entry.depth() > 1 && (is_build_dir(entry.path()) || ignores.is_ignored(entry.path(), true)) {
+            found.push(relative.to_path_buf());
+            return false;
+        }
+        true
+    });
+    walker.for_each(drop);
+
+    trace!("nested build directories: {found:#?}");
+    found
+}
+
+fn is_build_dir(dir: &Path) -> bool {
+    let (Some(name), Some(parent)) = (dir.file_name(), dir.parent()) else {
+        return false;
+    };
+    BUILD_DIRS
+        .iter()
+        .find(|(build_dir, _)| name == *build_dir)
+        .is_some_and(|(_, markers)| markers.is_empty() || markers.iter().any(|marker| parent.join(marker).is_file()))
+}
+
+/// Everything that decides about a root entry without the agent.
+struct Rules<'a> {
+    project_dir: &'a Path,
+    config: &'a BlacklistConfig,
+    ignores: Gitignore,
+}
+
+impl<'a> Rules<'a> {
+    fn new(project_dir: &'a Path, config: &'a BlacklistConfig) -> Self {
+        let mut builder = GitignoreBuilder::new(project_dir);
+        // later files win, like in git
+        for file in [".git/info/exclude", ".gitignore", ".ignore"] {
+            let path = project_dir.join(file);
+            if path.is_file() {
+                if let Some(e) = builder.add(&path) {
+                    warn!("skipping part of {}: {e}", path.display());
+                }
+            }
+        }
+        let ignores = builder.build().unwrap_or_else(|e| {
+            warn!("ignoring the ignore files: {e}");
+            Gitignore::empty()
+        });
+        Self { project_dir, config, ignores }
+    }
+
+    /// Whether to leave `entry` out, `None` when no rule knows.
+    fn classify(&self, entry: &str, is_dir: bool) -> Option<bool> {
+        let verdict = if self.config.keep.iter().any(|keep| keep == entry) {
+            Some(false)
+        } else if self.config.exclude.iter().any(|exclude| exclude == entry) {
+            Some(true)
+        } else {
+            match self.ignores.matched(entry, is_dir) {
+                Match::Ignore(_) => Some(true),
+                Match::Whitelist(_) => Some(false),
+                Match::None if !is_dir => Some(false),
+                Match::None if is_build_dir(&self.project_dir.join(entry)) => Some(true),
+                Match::None if SOURCE_DIRS.contains(&entry) => Some(false),
+                Match::None => None,
+            }
+        };
+        trace!("{entry}: {verdict:?}");
+        verdict
+    }
+}
+
+/// Root entries of the project, without the ones the index always leaves out like `.git`.
+fn get_root_entries(project_dir: &Path) -> anyhow::Result<Vec<String>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(project_dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if !is_excluded_path(Path::new(&name), &[]) {
+            entries.push(name);
+        }
+    }
+    // directory order differs between file systems, a stable order keeps requests reproducible
+    entries.sort();
+
+    trace!("root entries: {entries:#?}");
+
+    Ok(entries)
+}
+
+fn read_cache(path: &Path) -> BTreeMap<String, bool> {
+    let cache = fs::read_to_string(path).ok().and_then(|cache| from_str(&cache).ok());
+    cache.unwrap_or_default()
+}
+
+fn write_cache(path: &Path, cache: &BTreeMap<String, bool>) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(cache)?)?;
+    Ok(())
+}
+
+/// Asks the blacklist agent which of `entries` hold build files. Replies that don't parse are
+/// answered with a correction, up to [`MAX_ATTEMPTS`] times, after which nothing is left out.
+/// Entries the agent makes up are dropped.
+async fn ask(provider: &dyn Provider, entries: &[String], json_schema: bool) -> anyhow::Result<Option<Vec<String>>> {
+    // the schema holds the reply to an object, the agent is told to answer with one
+    let (prompt, example, correction) = if json_schema {
+        (BLACKLIST_SCHEMA_PROMPT, r#"{"entries": ["node_modules", "dist", "build"]}"#, SCHEMA_CORRECTION)
+    } else {
+        (BLACKLIST_PROMPT, r#"["node_modules", "dist", "build"]"#, CORRECTION)
+    };
+    let settings = AgentSettings::load("blacklist", prompt);
+    let mut agent = ai_agent! {
+        model: settings.model.clone(),
+        temperature: settings.temperature,
+        system_message: settings.system_prompt.as_str(),
+        messages: [
+            message!(system, user: "example_input", content: r#"[
+              "app.js",
+              "dist",
+              "build",
+              "package.json",
+              "README.md",
+              "public",
+              "views",
+              "routes",
+              "models",
+              "controllers",
+              "config",
+              "tests",
+              "node_modules"
+            ]"#),
+            message!(system, user: "example_response", content: example),
+            message!(user, content: serde_json::to_string_pretty(entries)?),
+        ],
+    };
+    agent.max_tokens = settings.max_tokens;
+    let mut request = CompletionRequest::new(&agent, &settings);
+    if json_schema {
+        request.response_format = Some(response_format(entries));
+    }
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let chat = provider.complete(&request).await?;
+        let reply = chat.choices.into_iter().next().ok_or_else(|| anyhow!("the blacklist agent got no reply"))?.message;
+
+        if let Some(mut answer) = reply.content.as_deref().and_then(parse_entries) {
+            answer.retain(|entry| {
+                let known = entries.contains(entry);
+                if !known {
+                    debug!("dropping {entry} from the blacklist, it is not a root entry");
+                }
+                known
+            });
+            debug!("blacklist agent: {answer:#?}");
+            return Ok(Some(answer));
+        }
+
+        debug!("blacklist agent reply {attempt} did not parse: {:?}", reply.content);
+        request.messages.push(reply);
+        request.messages.push(Message::user(correction));
+    }
+
+    warn!("the blacklist agent did not answer with the entries to leave out, asking it again next time");
+    Ok(None)
+}
+
+/// Holds the reply to an object with the entries to leave out, only the ones it was asked about.
+fn response_format(entries: &[String]) -> Value {
+    json!({
+        "type": "json_schema",
+        "json_schema": {
+            "name": "blacklist",
+            "strict": true,
+            "schema": {
+                "type": "object",
+                "properties": {
+                    "entries": { "type": "array", "items": { "type": "string", "enum": entries } }
+                },
+                "required": ["entries"],
+                "additionalProperties": false
+            }
+        }
+    })
+}
+
+/// The entries of a reply, a JSON array or an object with `entries`, also when it is wrapped in a
+/// code fence or prose. Entries like `./dist/` are read as `dist`.
+fn parse_entries(reply: &str) -> Option<Vec<String>> {
+    #[derive(Deserialize)]
+    struct Entries {
+        entries: Vec<String>,
+    }
+
+    let reply = reply.trim();
+    let entries = from_str::<Vec<String>>(reply)
+        .or_else(|_| from_str::<Entries>(reply).map(|e| e.entries))
+        .ok()
+        .or_else(|| {
+            let array = &reply[reply.find('[')?..=reply.rfind(']')?];
+            from_str::<Vec<String>>(array).ok()
+        })?;
+
+    let entries = entries.iter().map(|entry| entry.trim_start_matches("./").trim_end_matches('/').to_string());
+    Some(entries.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use serde_json::json;
+    use crate::ai::blacklist::{blacklist_of, nested_build_dirs, parse_entries, BlacklistConfig, Rules};
+    use crate::ai::blacklist::{BLACKLIST_SCHEMA_PROMPT, SCHEMA_CORRECTION};
+    use crate::ai::scripted::Scripted;
+
+    #[test]
+    fn rules_classify_without_the_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("Cargo.toml"), "").unwrap();
+        fs::create_dir_all(root.join(".git/info")).unwrap();
+        fs::write(root.join(".git/info/exclude"), "scratch/\n").unwrap();
+        fs::write(root.join(".gitignore"), "logs/\n*.log\n!dist/\n").unwrap();
+        fs::write(root.join(".ignore"), "generated/\n").unwrap();
+        let config = BlacklistConfig {
+            exclude: vec!["fixtures".to_string()],
+            keep: vec!["logs".to_string()],
+            ..Default::default()
+        };
+        let rules = Rules::new(root, &config);
+
+        for excluded in ["scratch", "generated", "target", "node_modules", "fixtures"] {
+            assert_eq!(rules.classify(excluded, true), Some(true), "{excluded}");
+        }
+        assert_eq!(rules.classify("debug.log", false), Some(true));
+        for kept in ["logs", "dist", "src", "Cargo.toml"] {
+            assert_eq!(rules.classify(kept, kept != "Cargo.toml"), Some(false), "{kept}");
+        }
+        assert_eq!(rules.classify("bundles", true), None);
+        // without a `package.json` or the like, `build` and `deps` may well hold sources
+        assert_eq!(rules.classify("build", true), None);
+        assert_eq!(rules.classify("deps", true), None);
+    }
+
+    #[test]
+    fn finds_build_directories_next_to_their_markers() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for file in [
+            "crates/core/Cargo.toml",
+            "crates/core/target/debug/core",
+            "packages/web/package.json",
+            "packages/web/node_modules/react/index.js",
+            "packages/web/dist/app.js",
+            "packages/app/.gitignore",
+            "packages/app/generated/api.ts",
+            "docs/build/index.md",
+            "vendor/lib/node_modules/x.js",
+        ] {
+            fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+            fs::write(root.join(file), "").unwrap();
+        }
+        fs::write(root.join("packages/app/.gitignore"), "generated/\n").unwrap();
+
+        assert_eq!(
+            nested_build_dirs(root, &[Path::new("vendor")]),
+            [
+                "crates/core/target",
+                "packages/app/generated",
+                "packages/web/dist",
+                "packages/web/node_modules",
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[tokio::test]
+    async fn asks_the_model_once_about_unknown_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for entry in ["src", "target", "bundles", "protos", ".git"] {
+            fs::create_dir_all(root.join(entry)).unwrap();
+        }
+        fs::write(root.join("Cargo.toml"), "").unwrap();
+        let model = Scripted::answers([r#"["bundles"]"#]);
+        let config = BlacklistConfig::default();
+
+        let expected = vec![PathBuf::from("bundles"), PathBuf::from("target")];
+        assert_eq!(blacklist_of(Some(&model), root, &config, false).await.unwrap(), expected);
+        assert_eq!(blacklist_of(Some(&model), root, &config, false).await.unwrap(), expected);
+        assert_eq!(blacklist_of(None, root, &config, false).await.unwrap(), expected);
+        assert_eq!(model.requests().len(), 1);
+
+        let offline = BlacklistConfig { ask_model: Some(false), ..Default::default() };
+        fs::create_dir(root.join("artifacts")).unwrap();
+        assert_eq!(blacklist_of(Some(&model), root, &offline, false).await.unwrap(), expected);
+        assert_eq!(model.requests().len(), 1);
+    }
+
+    #[test]
+    fn reads_entries_out_of_loose_replies() {
+        let entries = |reply| parse_entries(reply).unwrap();
+
+        assert_eq!(entries(r#"["dist"]"#), ["dist"]);
+        assert_eq!(entries(r#"{"entries": ["dist"]}"#), ["dist"]);
+        assert_eq!(entries("```json\n[\"./dist/\", \"build\"]\n```"), ["dist", "build"]);
+        assert_eq!(entries("These hold build files: [\"dist\"]. The rest is source."), ["dist"]);
+        assert_eq!(parse_entries("dist and build"), None);
+    }
+
+    #[tokio::test]
+    async fn corrects_the_model_until_its_answer_parses() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("bundles")).unwrap();
+        let model = Scripted::answers(["bundles looks like build output", r#"The answer: ["bundles", "made_up"]"#]);
+
+        let excluded = blacklist_of(Some(&model), root, &BlacklistConfig::default(), true).await.unwrap();
+
+        assert_eq!(excluded, [PathBuf::from("bundles")]);
+        let requests = model.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].messages.last().unwrap().content.as_deref(), Some(SCHEMA_CORRECTION));
+        assert_eq!(requests[0].messages[0].content.as_deref(), Some(BLACKLIST_SCHEMA_PROMPT));
+        let schema = &requests[0].response_format.as_ref().unwrap()["json_schema"]["schema"];
+        assert_eq!(schema["properties"]["entries"]["items"]["enum"], json!(["bundles"]));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_rules_when_the_model_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("bundles")).unwrap();
+        fs::create_dir(root.join("node_modules")).unwrap();
+        let model = Scripted::answers([]);
+
+        let excluded = blacklist_of(Some(&model), root, &BlacklistConfig::default(), false).await.unwrap();
+
+        assert_eq!(excluded, [PathBuf::from("node_modules")]);
+        assert_eq!(model.requests().len(), 1);
+        assert!(!root.join(".devgpt/blacklist.json").exists());
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_the_model_without_caching() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("bundles")).unwrap();
+        let model = Scripted::answers(["no", "still no", "never"]);
+
+        assert!(blacklist_of(Some(&model), root, &BlacklistConfig::default(), false).await.unwrap().is_empty());
+        assert_eq!(model.requests().len(), 3);
+        assert!(!root.join(".devgpt/blacklist.json").exists());
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use log::{trace, warn};
+use serde_derive::{Deserialize, Serialize};
+use tiktoken_rs::CoreBPE;
+use crate::ai::provider::CompletionRequest;
+use crate::ai::wire::Message;
+use crate::tiktoken::{bpe_for, TokensLen};
+
+/// Rounds the finder gets when `max_rounds` isn't configured.
+pub const DEFAULT_MAX_ROUNDS: u32 = 25;
+
+/// Tokens a single tool result may take up when `max_result_tokens` isn't configured.
+pub const DEFAULT_MAX_RESULT_TOKENS: usize = 2000;
+
+/// Dollars per 1000 prompt and completion tokens, matched by model name prefix, most specific first.
+const PRICES: &[(&str, Price)] = &[
+    ("gpt-4-1106-preview", Price { prompt: 0.01, completion: 0.03 }),
+    ("gpt-4-32k", Price { prompt: 0.06, completion: 0.12 }),
+    ("gpt-4", Price { prompt: 0.03, completion: 0.06 }),
+    ("gpt-3.5-turbo-1106", Price { prompt: 0.001, completion: 0.002 }),
+    ("gpt-3.5-turbo", Price { prompt: 0.0015, completion: 0.002 }),
+];
+
+/// The `[search]` section, limits of a single search.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct SearchConfig {
+    /// Requests the finder may make, defaults to 25.
+    #[serde(default)]
+    pub max_rounds: Option<u32>,
+    /// Tokens the finder may spend over all of its requests and replies.
+    #[serde(default)]
+    pub max_total_tokens: Option<usize>,
+    /// Dollars the finder may spend, needs the price of the model.
+    #[serde(default)]
+    pub max_cost: Option<f64>,
+    /// Tokens a single page of tool results may take up, defaults to 2000.
+    #[serde(default)]
+    pub max_result_tokens: Option<usize>,
+    /// Adds an overview of the directories of the project to the question, so the finder knows
+    /// the layout before it starts searching.
+    #[serde(default)]
+    pub overview: bool,
+    /// Dollars per 1000 prompt tokens, for models without a built-in price.
+    #[serde(default)]
+    pub prompt_price: Option<f64>,
+    /// Dollars per 1000 completion tokens, for models without a built-in price.
+    #[serde(default)]
+    pub completion_price: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Price {
+    prompt: f64,
+    completion: f64,
+}
+
+impl Price {
+    fn of(config: &SearchConfig, model: &str) -> Option<Self> {
+        let known = PRICES.iter().find(|(prefix, _)| model.starts_with(prefix)).map(|(_, price)| *price);
+        match (config.prompt_price, config.completion_price) {
+            (None, None) => known,
+            (prompt, completion) => Some(Self {
+                prompt: prompt.or(known.map(|p| p.prompt)).unwrap_or_default(),
+                completion: completion.or(known.map(|p| p.completion)).unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+/// Why a search ended.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The finder called `stop_searching`.
+    Answered,
+    MaxRounds(u32),
+    MaxTotalTokens(usize),
+    MaxCost(f64),
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Answered => write!(f, "the finder stopped searching"),
+            StopReason::MaxRounds(max) => write!(f, "reached the limit of {max} rounds"),
+            StopReason::MaxTotalTokens(max) => write!(f, "reached the limit of {max} tokens"),
+            StopReason::MaxCost(max) => write!(f, "reached the budget of ${max}"),
+        }
+    }
+}
+
+/// Keeps track of what a search has spent so far, tokens are counted with tiktoken.
+pub struct Budget {
+    max_rounds: u32,
+    max_total_tokens: Option<usize>,
+    max_cost: Option<f64>,
+    price: Option<Price>,
+    bpe: Arc<CoreBPE>,
+    rounds: u32,
+    tokens: usize,
+    cost: f64,
+}
+
+impl Budget {
+    pub fn new(config: &SearchConfig, model: &str) -> Self {
+        let price = Price::of(config, model);
+        if config.max_cost.is_some() && price.is_none() {
+            warn!("no price known for {model}, set `search.prompt_price` and `search.completion_price` to limit the cost");
+        }
+
+        Self {
+            max_rounds: config.max_rounds.unwrap_or(DEFAULT_MAX_ROUNDS),
+            max_total_tokens: config.max_total_tokens,
+            max_cost: config.max_cost,
+            price,
+            bpe: Arc::new(bpe_for(model)),
+            rounds: 0,
+            tokens: 0,
+            cost: 0.0,
+        }
+    }
+
+    /// Counts `request` against the budget, or returns the limit it would break without counting it.
+    pub fn admit(&mut self, request: &CompletionRequest) -> Option<StopReason> {
+        if self.rounds >= self.max_rounds {
+            return Some(StopReason::MaxRounds(self.max_rounds));
+        }
+
+        let tokens = request.token_len(&self.bpe);
+        if let Some(max) = self.max_total_tokens.filter(|max| self.tokens + tokens > *max) {
+            return Some(StopReason::MaxTotalTokens(max));
+        }
+
+        let cost = self.price.map_or(0.0, |p| p.prompt * tokens as f64 / 1000.0);
+        if let Some(max) = self.max_cost.filter(|max| self.price.is_some() && self.cost + cost > *max) {
+            return Some(StopReason::MaxCost(max));
+        }
+
+        self.rounds += 1;
+        self.tokens += tokens;
+        self.cost += cost;
+        None
+    }
+
+    /// The tokenizer of the model the budget counts for, shared with the tools that count tokens.
+    pub fn bpe(&self) -> &Arc<CoreBPE> {
+        &self.bpe
+    }
+
+    /// Counts the reply to an admitted request.
+    pub fn spend(&mut self, reply: &Message) {
+        let tokens = reply.token_len(&self.bpe);
+        self.tokens += tokens;
+        self.cost += self.price.map_or(0.0, |p| p.completion * tokens as f64 / 1000.0);
+        trace!("spent {} rounds, {} tokens, ${:.4}", self.rounds, self.tokens, self.cost);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openai_macros::{ai_agent, message};
+    use crate::ai::AgentSettings;
+    use crate::ai::budget::{Budget, Price, SearchConfig, StopReason};
+    use crate::ai::provider::CompletionRequest;
+
+    fn request(content: &str) -> CompletionRequest {
+        let agent = ai_agent! { model: "gpt-4", messages: message!(user, content: content) };
+        let settings = AgentSettings {
+            model: "gpt-4".to_string(),
+            temperature: 0.0,
+            max_tokens: None,
+            seed: None,
+            system_prompt: String::new(),
+        };
+        CompletionRequest::new(&agent, &settings)
+    }
+
+    #[test]
+    fn stops_at_the_first_limit() {
+        let config = SearchConfig { max_rounds: Some(2), ..Default::default() };
+        let mut budget = Budget::new(&config, "gpt-4");
+        assert_eq!(budget.admit(&request("one")), None);
+        assert_eq!(budget.admit(&request("two")), None);
+        assert_eq!(budget.admit(&request("three")), Some(StopReason::MaxRounds(2)));
+
+        let config = SearchConfig { max_total_tokens: Some(30), ..Default::default() };
+        let mut budget = Budget::new(&config, "gpt-4");
+        assert_eq!(budget.admit(&request("short")), None);
+        budget.spend(&message!(assistant, content: "a reply").into());
+        assert_eq!(budget.admit(&request(&"long ".repeat(20))), Some(StopReason::MaxTotalTokens(30)));
+
+        let config = SearchConfig { max_cost: Some(0.0001), ..Default::default() };
+        let mut budget = Budget::new(&config, "gpt-4");
+        assert_eq!(budget.admit(&request(&"long ".repeat(20))), Some(StopReason::MaxCost(0.0001)));
+    }
+
+    #[test]
+    fn configured_prices_override_the_table() {
+        let config = SearchConfig { completion_price: Some(1.0), ..Default::default() };
+        assert_eq!(Price::of(&config, "gpt-4-0613"), Some(Price { prompt: 0.03, completion: 1.0 }));
+        assert_eq!(Price::of(&SearchConfig::default(), "llama"), None);
+    }
+}
@@ -0,0 +1,144 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use log::{debug, trace};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::ai::provider::{CompletionRequest, Provider};
+use crate::ai::wire::Completion;
+
+/// Stands in for the project directory, so cassettes replay in any checkout.
+const PROJECT_DIR_PLACEHOLDER: &str = "$PROJECT_DIR";
+/// Fields that don't change the answer and are left out when matching requests.
+const IGNORED_FIELDS: &[&str] = &["stream", "user"];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Tape {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    request: Value,
+    response: Value,
+}
+
+enum Mode {
+    Record(Box<dyn Provider>),
+    Replay { used: Mutex<Vec<bool>> },
+}
+
+/// Records every request and response of a session to a file, or replays a recorded session
+/// without touching the network. Requests are matched on their normalized content.
+pub struct Cassette {
+    path: PathBuf,
+    project_dir: Option<String>,
+    mode: Mode,
+    tape: Mutex<Tape>,
+}
+
+impl Cassette {
+    /// Passes requests on to `inner` and writes them to `path` as they happen.
+    pub fn record(path: &Path, inner: Box<dyn Provider>, project_dir: Option<&Path>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            project_dir: project_dir.map(|p| p.display().to_string()),
+            mode: Mode::Record(inner),
+            tape: Default::default(),
+        }
+    }
+
+    pub fn replay(path: &Path, project_dir: Option<&Path>) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path).with_context(|| format!("could not read cassette {}", path.display()))?;
+        let tape: Tape = serde_json::from_str(&contents).with_context(|| format!("invalid cassette {}", path.display()))?;
+        debug!("replaying {} interactions from {}", tape.interactions.len(), path.display());
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            project_dir: project_dir.map(|p| p.display().to_string()),
+            mode: Mode::Replay { used: Mutex::new(vec![false; tape.interactions.len()]) },
+            tape: Mutex::new(tape),
+        })
+    }
+
+    fn normalize(&self, request: &CompletionRequest) -> anyhow::Result<Value> {
+        let mut value = serde_json::to_value(request)?;
+        if let Value::Object(map) = &mut value {
+            for field in IGNORED_FIELDS {
+                map.remove(*field);
+            }
+        }
+        Ok(map_strings(value, &|s| {
+            self.hide_project_dir(s).split_whitespace().collect::<Vec<_>>().join(" ")
+        }))
+    }
+
+    fn hide_project_dir(&self, s: &str) -> String {
+        match &self.project_dir {
+            Some(dir) => s.replace(dir.as_str(), PROJECT_DIR_PLACEHOLDER),
+            None => s.to_string(),
+        }
+    }
+
+    fn restore_project_dir(&self, s: &str) -> String {
+        match &self.project_dir {
+            Some(dir) => s.replace(PROJECT_DIR_PLACEHOLDER, dir),
+            None => s.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for Cassette {
+    async fn complete(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
+        let normalized = self.normalize(request)?;
+
+        match &self.mode {
+            Mode::Record(inner) => {
+                let chat = inner.complete(request).await?;
+                let response = map_strings(serde_json::to_value(&chat)?, &|s| self.hide_project_dir(s));
+
+                let mut tape = self.tape.lock().unwrap();
+                tape.interactions.push(Interaction { request: normalized, response });
+                fs::write(&self.path, serde_json::to_string_pretty(&*tape)?)
+                    .with_context(|| format!("could not write cassette {}", self.path.display()))?;
+                trace!("recorded interaction {}", tape.interactions.len());
+
+                Ok(chat)
+            }
+            Mode::Replay { used } => {
+                let tape = self.tape.lock().unwrap();
+                let mut used = used.lock().unwrap();
+
+                let i = tape
+                    .interactions
+                    .iter()
+                    .enumerate()
+                    .position(|(i, interaction)| !used[i] && interaction.request == normalized)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "no recorded response in {} for request {}",
+                            self.path.display(),
+                            serde_json::to_string(&normalized).unwrap_or_default()
+                        )
+                    })?;
+                used[i] = true;
+                trace!("replaying interaction {}", i + 1);
+
+                let response = map_strings(tape.interactions[i].response.clone(), &|s| self.restore_project_dir(s));
+                Ok(serde_json::from_value(response)?)
+            }
+        }
+    }
+}
+
+fn map_strings(value: Value, f: &impl Fn(&str) -> String) -> Value {
+    match value {
+        Value::String(s) => Value::String(f(&s)),
+        Value::Array(values) => Value::Array(values.into_iter().map(|v| map_strings(v, f)).collect()),
+        Value::Object(map) => Value::Object(map.into_iter().map(|(k, v)| (k, map_strings(v, f))).collect()),
+        value => value,
+    }
+}
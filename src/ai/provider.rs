@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use log::trace;
+use openai_utils::AiAgent;
+use serde_derive::{Deserialize, Serialize};
+use crate::ai::cassette::Cassette;
+use crate::ai::wire::{Completion, Message, ToolSpec};
+use crate::ai::AgentSettings;
+use crate::config::CONFIG;
+
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+pub const DEFAULT_API_KEY_ENV: &str = "OPENAI_API_KEY";
+
+/// A request as it goes over the wire, the chat request of `openai-utils` with tools instead of
+/// functions, plus the fields it doesn't know about.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<ToolSpec>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Holds the reply to a JSON schema, only for apis that support it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
+}
+
+impl CompletionRequest {
+    pub fn new(agent: &AiAgent, settings: &AgentSettings) -> Self {
+        let chat = agent.build_request(false);
+        Self {
+            model: chat.model,
+            messages: chat.messages.into_iter().map(Message::from).collect(),
+            tools: None,
+            temperature: chat.temperature,
+            max_tokens: chat.max_tokens,
+            seed: settings.seed,
+            response_format: None,
+        }
+    }
+}
+
+/// Something that answers chat completion requests.
+#[async_trait]
+pub trait Provider: Send + Sync {
+    async fn complete(&self, request: &CompletionRequest) -> anyhow::Result<Completion>;
+}
+
+/// The provider set up in the `[provider]` section, wrapped in a cassette when one is given.
+pub fn configured() -> anyhow::Result<Box<dyn Provider>> {
+    let config = CONFIG.read().unwrap();
+    let project_dir = config.project_dir.as_deref();
+    let provider = &config.provider;
+
+    if let Some(path) = &provider.replay {
+        return Ok(Box::new(Cassette::replay(path, project_dir)?));
+    }
+
+    let api = Box::new(OpenAiCompatible::from_config(provider));
+    Ok(match &provider.record {
+        Some(path) => Box::new(Cassette::record(path, api, project_dir)),
+        None => api,
+    })
+}
+
+/// The `[provider]` section.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProviderConfig {
+    /// Base url of an OpenAI compatible api, `/chat/completions` is appended to it.
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Environment variable holding the api key, local servers often don't need one.
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// Extra headers sent with every request.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Cassette to write every request and response of the session to.
+    #[serde(default)]
+    pub record: Option<PathBuf>,
+    /// Cassette to answer requests from instead of the api, nothing goes over the network.
+    #[serde(default)]
+    pub replay: Option<PathBuf>,
+    /// The api takes a JSON schema as `response_format`, like the newer OpenAI models do. Agents
+    /// that answer in JSON then get replies that always parse.
+    #[serde(default)]
+    pub json_schema: bool,
+}
+
+/// The OpenAI api or anything speaking the same protocol, like llama.cpp, vLLM or Ollama.
+#[derive(Debug, Clone)]
+pub struct OpenAiCompatible {
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+    headers: BTreeMap<String, String>,
+}
+
+impl OpenAiCompatible {
+    pub fn new(base_url: &str, api_key: Option<String>, headers: BTreeMap<String, String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            api_key,
+            headers,
+        }
+    }
+
+    pub fn from_config(config: &ProviderConfig) -> Self {
+        let key_env = config.api_key_env.as_deref().unwrap_or(DEFAULT_API_KEY_ENV);
+        Self::new(
+            config.base_url.as_deref().unwrap_or(DEFAULT_BASE_URL),
+            env::var(key_env).ok(),
+            config.headers.clone(),
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+#[async_trait]
+impl Provider for OpenAiCompatible {
+    async fn complete(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
+        trace!("request body: {}", serde_json::to_string_pretty(request)?);
+
+        let mut req = self.client.post(&self.url).json(request);
+        if let Some(key) = &self.api_key {
+            req = req.bearer_auth(key);
+        }
+        for (name, value) in &self.headers {
+            req = req.header(name, value);
+        }
+
+        let res = req.send().await.with_context(|| format!("could not reach {}", self.url))?;
+        let status = res.status();
+        let body = res.text().await?;
+        trace!("response body: {body}");
+
+        if !status.is_success() {
+            let message = serde_json::from_str::<ErrorResponse>(&body)
+                .map(|e| e.error.message)
+                .unwrap_or(body);
+            return Err(anyhow!("{} returned {status}: {message}", self.url));
+        }
+
+        serde_json::from_str(&body).with_context(|| format!("unexpected response from {}", self.url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use openai_macros::{ai_agent, message};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use crate::ai::AgentSettings;
+    use crate::ai::provider::{CompletionRequest, OpenAiCompatible, Provider};
+
+    /// Answers a single request with `body` and hands back the raw request it received.
+    async fn serve_once(body: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1/", listener.local_addr().unwrap());
+
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, rest)) = text.split_once("\r\n\r\n") {
+                    let len = head
+                        .lines()
+                        .find_map(|l| l.to_lowercase().strip_prefix("content-length: ").map(|v| v.parse::<usize>().unwrap()))
+                        .unwrap_or(0);
+                    if rest.len() >= len {
+                        break;
+                    }
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn talks_to_a_compatible_server() {
+        let (url, server) = serve_once(
+            r#"{"id":"1","object":"chat.completion","created":0,"model":"local","choices":[{"index":0,"message":{"role":"assistant","content":"hi"},"finish_reason":"stop"}],"usage":{"prompt_tokens":1,"completion_tokens":1,"total_tokens":2}}"#,
+        )
+        .await;
+
+        let headers = BTreeMap::from([("x-team".to_string(), "search".to_string())]);
+        let provider = OpenAiCompatible::new(&url, Some("secret".to_string()), headers);
+        let agent = ai_agent! { model: "local", messages: message!(user, content: "hello") };
+        let settings = AgentSettings {
+            model: "local".to_string(),
+            temperature: 0.0,
+            max_tokens: None,
+            seed: Some(3),
+            system_prompt: String::new(),
+        };
+
+        let chat = provider.complete(&CompletionRequest::new(&agent, &settings)).await.unwrap();
+        let request = server.await.unwrap().to_lowercase();
+
+        assert_eq!(chat.choices[0].message.content.as_deref(), Some("hi"));
+        assert!(request.starts_with("post /v1/chat/completions "));
+        assert!(request.contains("authorization: bearer secret"));
+        assert!(request.contains("x-team: search"));
+        assert!(request.contains(r#""seed":3"#));
+    }
+}
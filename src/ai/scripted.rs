@@ -0,0 +1,49 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use crate::ai::provider::{CompletionRequest, Provider};
+use crate::ai::wire::Completion;
+
+/// Stands in for the api in tests, answers with scripted messages in order and keeps the requests
+/// it got. Once the messages run out it fails like an api that can't be reached.
+pub struct Scripted {
+    replies: Mutex<VecDeque<Value>>,
+    requests: Mutex<Vec<CompletionRequest>>,
+}
+
+impl Scripted {
+    pub fn new(replies: impl IntoIterator<Item = Value>) -> Self {
+        Self { replies: Mutex::new(replies.into_iter().collect()), requests: Mutex::default() }
+    }
+
+    /// Answers with assistant messages of only `contents`.
+    pub fn answers(contents: impl IntoIterator<Item = &'static str>) -> Self {
+        Self::new(contents.into_iter().map(|content| json!({ "role": "assistant", "content": content })))
+    }
+
+    pub fn requests(&self) -> Vec<CompletionRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The last message of the last request.
+    pub fn last_message(&self) -> String {
+        let requests = self.requests.lock().unwrap();
+        let messages = &requests.last().unwrap().messages;
+        messages.last().unwrap().content.clone().unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl Provider for Scripted {
+    async fn complete(&self, request: &CompletionRequest) -> anyhow::Result<Completion> {
+        self.requests.lock().unwrap().push(request.clone());
+        let message = self.replies.lock().unwrap().pop_front().ok_or_else(|| anyhow!("no scripted reply left"))?;
+        Ok(serde_json::from_value(json!({
+            "id": "1", "object": "chat.completion", "created": 0, "model": "scripted",
+            "choices": [{ "index": 0, "message": message, "finish_reason": "stop" }],
+            "usage": { "prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0 }
+        }))?)
+    }
+}
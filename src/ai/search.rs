@@ -1,214 +1,539 @@
-use std::cell::RefCell;
-use std::io::Write;
-use std::io::stdout;
-use std::ops::Range;
 use std::path::{Path, PathBuf};
-use log::{debug, trace};
+use std::sync::Arc;
+use anyhow::anyhow;
+use futures_util::future::join_all;
+use log::{debug, info, trace};
 use openai_macros::{ai_agent, message};
-use openai_utils::{FunctionCall, NoArgs};
-use schemars::JsonSchema;
-use serde_derive::{Deserialize, Serialize};
-use serde_json::{from_str, to_string_pretty};
-use crate::ai::blacklist;
-use crate::{as_paths, print_chat};
+use openai_utils::FunctionCall;
+use tiktoken_rs::CoreBPE;
+use crate::as_paths;
+use crate::ai::AgentSettings;
+use crate::ai::budget::{Budget, StopReason, DEFAULT_MAX_RESULT_TOKENS};
+use crate::ai::provider::{CompletionRequest, Provider};
+use crate::ai::tool::{CallError, Project, Registry};
+use crate::ai::wire::Message;
 use crate::config::CONFIG;
-use crate::ctags::{Ctag, CtagsOutput};
+use crate::ctags::{is_excluded_path, Ctag, CtagsOutput, PathPattern};
+use results::{candidates, finish};
+use sets::ResultSets;
+use tools::{finder_tools, PageArgs, ResultArgs};
+use tree::list_dir;
+use thiserror::Error;
+use tokio::task::spawn_blocking;
 
-struct Context {
-    file: PathBuf,
-    lines: Range<u64>,
-    code: String,
+pub use results::FoundFile;
+
+mod context;
+mod grep;
+mod results;
+mod sets;
+mod tools;
+mod tree;
+
+/// A mistake in a tool call the finder can fix, told to it instead of ending the search.
+#[derive(Debug, Error)]
+pub enum ToolError {
+    #[error("there is no result set named `{name}`, the sets are: {known}")]
+    UnknownSet { name: String, known: String },
+    #[error("{0} needs at least one set")]
+    NoSets(&'static str),
+    #[error("could not read {}, {reason}", path.display())]
+    Unreadable { path: PathBuf, reason: String },
+    #[error("invalid pattern: {0}")]
+    InvalidPattern(String),
 }
-pub async fn find_file(search: &str, blacklist: Vec<PathBuf>) -> anyhow::Result<Option<Vec<PathBuf>>> {
-    // create ai agent with system and add functions for searching.
-    let mut finder = ai_agent! {
-        model: "gpt-4-1106-preview",
-        system_message: include_str!("finder.md"),
-        temperature: 0.0,
-        messages: message!(user, content: format!("{search}"))
-    };
-    
-    trace!("tags: {:#?}", CtagsOutput::get_tags(&as_paths(&blacklist)));
 
-    let result = RefCell::new(CtagsOutput(vec![]));
+/// What a search came up with and why it ended, the results the finder is most sure about first.
+/// When a limit ended it, `found` holds the files of the last search results instead of an answer
+/// of the finder.
+#[derive(Debug, Clone)]
+pub struct SearchOutcome {
+    pub found: Option<Vec<FoundFile>>,
+    pub reason: StopReason,
+}
 
-    let find_name = |args: FindNameArgs| {
-        let tags = CtagsOutput::get_tags(&as_paths(&blacklist));
-        // Borrow `result` mutably and replace its contents
-        *result.borrow_mut() = CtagsOutput(tags.0.iter().filter(|t| t.name_contains(&args.name)).cloned().collect());
-    };
-    
-    finder.push_function(&find_name, "find_name");
-    
-    let find_path = |args: FindPathArgs| {
-        let tags = CtagsOutput::get_tags(&as_paths(&blacklist));
-        *result.borrow_mut() = CtagsOutput(tags.0.iter().filter(|t| t.path_is(&args.path)).cloned().collect());
-    };
-    
-    finder.push_function(&find_path, "find_path");
-    
-    let find_kind = |args: FindKindArgs| {
-        let tags = CtagsOutput::get_tags(&as_paths(&blacklist));
-        result.borrow_mut().0.extend(tags.0.iter().filter(|t| t.kind_contains(&args.kind)).cloned());
+/// Tags a tool found, saved as a result set the finder is shown a page of.
+struct Found {
+    tags: Vec<Ctag>,
+    save_as: Option<String>,
+    page: PageArgs,
+}
+
+/// What a tool call came up with.
+enum Outcome {
+    Found(Found),
+    Reply(String),
+    /// The finder is done, with its answer.
+    Stop(Option<Vec<ResultArgs>>),
+}
+
+impl From<Found> for Outcome {
+    fn from(found: Found) -> Self {
+        Outcome::Found(found)
+    }
+}
+
+impl From<String> for Outcome {
+    fn from(reply: String) -> Self {
+        Outcome::Reply(reply)
+    }
+}
+
+pub async fn find_file(provider: &dyn Provider, search: &str, blacklist: Vec<PathBuf>) -> anyhow::Result<SearchOutcome> {
+    let settings = AgentSettings::load("finder", include_str!("finder.md"));
+    let (project_dir, search_config) = {
+        let config = CONFIG.read().unwrap();
+        let project_dir = config.project_dir.clone().ok_or_else(|| anyhow!("no project directory configured"))?;
+        (project_dir, config.search.clone())
     };
-    
-    finder.push_function(&find_kind, "find_kind");
-    
-    let find_line_range = |args: FindLineRangeArgs| {
-        let tags = CtagsOutput::get_tags(&as_paths(&blacklist));
-        *result.borrow_mut() = CtagsOutput(tags.0.iter().filter(|t| {
-            if let Some(line) = t.line {
-                line >= args.from && line <= args.to
-            } else {
-                false
-            }
-        }).cloned().collect());
+    let mut budget = Budget::new(&search_config, &settings.model);
+    let max_result_tokens = search_config.max_result_tokens.unwrap_or(DEFAULT_MAX_RESULT_TOKENS);
+
+    let mut question = search.to_string();
+    if search_config.overview {
+        let overview = list_dir(&project_dir, &as_paths(&blacklist), Path::new(""), DEFAULT_LIST_DEPTH, false, budget.bpe(), max_result_tokens)?;
+        question.push_str(&format!("\n\nThe directories of the project:\n{overview}"));
+    }
+
+    // create ai agent with system and add the tools for searching.
+    let mut finder = ai_agent! {
+        model: settings.model.clone(),
+        system_message: settings.system_prompt.as_str(),
+        temperature: settings.temperature,
+        messages: message!(user, content: question)
     };
-    
-    finder.push_function(&find_line_range, "find_line_range");
-    
-    let mut stop = false;
+    finder.max_tokens = settings.max_tokens;
+    let tools = Arc::new(finder_tools());
+    let mut request = CompletionRequest::new(&finder, &settings);
+    request.tools = Some(tools.specs());
 
+    // no tool changes files, so the index is refreshed once for the whole search
+    let tags = Arc::new(project_tags(&blacklist).await?);
+    trace!("tags: {tags:#?}");
+
+    let mut sets = ResultSets::default();
     let mut response = None;
 
+    let reason = loop {
+        if let Some(reason) = budget.admit(&request) {
+            break reason;
+        }
 
-    while !stop {
-        let mut stop_searching = |args: StopSearchingArgs| {
-            response = args.predicate_path;
-            stop = true;
-        };
+        let res = provider.complete(&request).await?;
+        let message = res.choices.into_iter().next().ok_or_else(|| anyhow!("the finder got no reply"))?.message;
+        budget.spend(&message);
+        if let Some(content) = &message.content {
+            // the model's remarks are logged so stdout only carries the results
+            info!("{content}");
+        }
+        let calls = message.tool_calls().to_vec();
+        request.messages.push(message);
+        if calls.is_empty() {
+            request.messages.push(Message::user(NO_TOOL_CALLS));
+            continue;
+        }
+
+        // the calls of a turn run at the same time, on the result sets as they were before it
+        let turn = Arc::new(Turn {
+            project_dir: project_dir.clone(),
+            blacklist: blacklist.clone(),
+            tags: tags.clone(),
+            sets: sets.clone(),
+            bpe: budget.bpe().clone(),
+            max_result_tokens,
+        });
+        // the tools read files, so each call gets a blocking thread instead of a worker of the runtime
+        let outcomes = join_all(calls.iter().map(|call| {
+            let (tools, turn, function) = (tools.clone(), turn.clone(), call.function.clone());
+            spawn_blocking(move || execute(&tools, &function, &turn))
+        }))
+        .await;
 
-        finder.push_function(&stop_searching, "stop_searching");
-
-        let mut receiver = finder.create_stream().await?;
-        print_chat!(receiver);
-        let res = receiver.construct_chat().await?;
-
-        finder.push_message(res.choices[0].clone().message);
-
-        if let Some(FunctionCall { name, arguments }) = res.choices[0].clone().message.function_call {
-            debug!("Function call received: {} with arguments: {}", name, arguments);
-            match name.as_str() {
-                "find_name" => {
-                    if let Ok(args) = from_str(&arguments) {
-                        debug!("Executing find_name with args: {:?}", args);
-                        find_name(args);
-                        finder.push_message(message!(system, content: format!("search results: {:?}", result.borrow_mut().0)));
-                    } else {
-                        finder.push_message(message!(system, content: "could not parse find_name arguments"));
-                    }
-                },
-                "find_path" => {
-                    if let Ok(args) = from_str(&arguments) {
-                        debug!("Executing find_path with args: {:?}", args);
-                        find_path(args);
-                        finder.push_message(message!(system, content: format!("search result: {}", to_string_pretty(&result.borrow().0)?)));
-                    } else {
-                        finder.push_message(message!(system, content: "could not parse find_path arguments"));
-                    }
-                },
-                "find_kind" => {
-                    if let Ok(args) = from_str(&arguments) {
-                        debug!("Executing find_kind with args: {:?}", args);
-                        find_kind(args);
-                        finder.push_message(message!(system, content: format!("search result: {}", to_string_pretty(&result.borrow().0)?)));
-                    } else {
-                        finder.push_message(message!(system, content: "could not parse find_kind arguments"));
-                    }
-                },
-                "find_line_range" => {
-                    if let Ok(args) = from_str(&arguments) {
-                        debug!("Executing find_line_range with args: {:?}", args);
-                        find_line_range(args);
-                        finder.push_message(message!(system, content: format!("search result: {}", to_string_pretty(&result.borrow().0)?)));
-                    } else {
-                        finder.push_message(message!(system, content: "could not parse find_line_range arguments"));
-                    }
-                },
-                "stop_searching" => {
-                    if let Ok(args) = from_str(&arguments) {
-                        debug!("Executing stop_searching with args: {:?}", args);
-                        stop_searching(args);
-                        finder.push_message(message!(system, content: format!("search result: {}", to_string_pretty(&result.borrow().0)?)));
-                    } else {
-                        finder.push_message(message!(system, content: "could not parse stop_searching arguments"));
-                    }
-                },
-                _ => {
-                    debug!("Function not found: {}", name);
-                    finder.push_message(message!(system, content: "function not found"));
-                },
-            }
-
-            debug!("Result after function call: {:#?}", result.borrow().0);
+        // their results are saved in the order of the calls
+        let mut stop = false;
+        for (call, outcome) in calls.iter().zip(outcomes) {
+            let reply = match outcome?? {
+                Outcome::Found(Found { tags, save_as, page }) => {
+                    let set = sets.save(save_as, tags);
+                    render_page(sets.get(&set)?, &set, page, budget.bpe(), max_result_tokens)?
+                }
+                Outcome::Reply(reply) => reply,
+                Outcome::Stop(results) => {
+                    response = results;
+                    stop = true;
+                    "search stopped".to_string()
+                }
+            };
+            request.messages.push(Message::tool(&call.id, reply));
         }
+        debug!("Result after the tool calls: {:#?}", sets.last());
+
+        if stop {
+            break StopReason::Answered;
+        }
+    };
+    debug!("search ended: {reason}");
+
+    let result = sets.last();
+    let mut found = match reason {
+        StopReason::Answered => response.map(|results| results.into_iter().map(|r| r.found(&project_dir, result)).collect()),
+        _ => Some(candidates(result)).filter(|found| !found.is_empty()),
+    };
+    if let Some(found) = &mut found {
+        finish(found, &project_dir, &as_paths(&blacklist), budget.bpe());
     }
 
-    Ok(response)
+    Ok(SearchOutcome { found, reason })
 }
 
-#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
-#[schemars(description = "Checks if the tag name contains the given name as argument")]
-struct FindNameArgs {
-    #[schemars(description = "The name to check if contained")]
-    name: String,
+/// The tags of the project, refreshing the index reads the files on a blocking thread.
+async fn project_tags(blacklist: &[PathBuf]) -> anyhow::Result<CtagsOutput> {
+    let blacklist = blacklist.to_vec();
+    spawn_blocking(move || CtagsOutput::get_tags(&as_paths(&blacklist))).await?
 }
 
-#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
-#[schemars(description = "Checks if the tag path is the path specified")]
-struct FindPathArgs {
-    #[schemars(description = "The path specified")]
-    path: PathBuf,
+/// Runs a tool call of the finder. Mistakes it can fix are told to it, anything else ends the search.
+fn execute(tools: &Registry<Turn, Outcome>, call: &FunctionCall, turn: &Turn) -> anyhow::Result<Outcome> {
+    match tools.call(call, turn) {
+        Err(e) if e.is::<CallError>() || e.is::<ToolError>() => Ok(Outcome::Reply(e.to_string())),
+        outcome => outcome,
+    }
+}
+
+/// What the tool calls of one turn of the finder work on.
+struct Turn {
+    project_dir: PathBuf,
+    blacklist: Vec<PathBuf>,
+    tags: Arc<CtagsOutput>,
+    sets: ResultSets,
+    bpe: Arc<CoreBPE>,
+    max_result_tokens: usize,
+}
+
+impl Project for Turn {
+    fn project_dir(&self) -> &Path {
+        &self.project_dir
+    }
+
+    fn blacklist(&self) -> &[PathBuf] {
+        &self.blacklist
+    }
+
+    fn bpe(&self) -> &CoreBPE {
+        &self.bpe
+    }
+
+    fn max_result_tokens(&self) -> usize {
+        self.max_result_tokens
+    }
 }
 
-#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
-#[schemars(description = "Checks if the tag kind contains the given kind as argument")]
-struct FindKindArgs {
-    #[schemars(description = "The kind to check if contained")]
-    kind: String,
+/// Resolves `path`, relative to `project_dir` or absolute, to a path that has to be inside the
+/// project and not blacklisted.
+fn resolve(project_dir: &Path, blacklist: &[&Path], path: &Path) -> Result<PathBuf, ToolError> {
+    let unreadable = |reason: String| ToolError::Unreadable { path: path.to_path_buf(), reason };
+
+    let project_dir = project_dir.canonicalize().map_err(|e| unreadable(e.to_string()))?;
+    let resolved = project_dir.join(path).canonicalize().map_err(|e| unreadable(e.to_string()))?;
+    let relative = resolved
+        .strip_prefix(&project_dir)
+        .map_err(|_| unreadable("it is outside of the project".to_string()))?;
+    if is_excluded_path(relative, blacklist) {
+        return Err(unreadable("it is blacklisted".to_string()));
+    }
+    Ok(resolved)
 }
 
-#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
-#[schemars(description = "Checks if a tag is within an inclusive range")]
-struct FindLineRangeArgs {
-    #[schemars(description = "the start of the range")]
-    from: u32,
-    #[schemars(description = "the end of the range")]
-    to: u32,
+/// Compiles a path the finder passed, a bad glob is a mistake it can fix.
+fn path_pattern(path: &str, project_dir: &Path) -> Result<PathPattern, ToolError> {
+    PathPattern::new(path, Some(project_dir)).map_err(|e| ToolError::InvalidPattern(e.to_string()))
 }
 
-#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
-#[schemars(description = "Run this function to stop the searching of the file where the predicate is found")]
-struct StopSearchingArgs {
-    #[schemars(description = "The file where the predicate is found, if not found set to none")]
-    predicate_path: Option<Vec<PathBuf>>
+/// Told to the finder when it answers without calling a tool, so it doesn't get asked the same again.
+const NO_TOOL_CALLS: &str = "Keep searching with the tools, or call `stop_searching` with your results when you are done.";
+
+/// Levels of directories `list_dir` and the overview show when the finder doesn't pass a `depth`.
+const DEFAULT_LIST_DEPTH: usize = 2;
+
+/// Results a tool shows when the finder doesn't pass a `limit`.
+const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Renders one page of the result set `set` for the finder, cut short once it would go over
+/// `max_tokens`. Says how many results there are in total and how many come after the page.
+fn render_page(result: &CtagsOutput, set: &str, page: PageArgs, bpe: &CoreBPE, max_tokens: usize) -> anyhow::Result<String> {
+    let total = result.0.len();
+    if page.offset >= total {
+        return Ok(format!("result set `{set}`: {total} results, none after offset {}", page.offset));
+    }
+
+    // a page shows at least one result, so `limit: 0` still gets somewhere
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE).max(1);
+    let page_tags = CtagsOutput(result.0.iter().skip(page.offset).take(limit).cloned().collect());
+    let (mut shown, mut rest) = page_tags.max_slice(bpe, max_tokens);
+    if shown.is_empty() {
+        // a single tag over the budget is still shown, otherwise paging would never get past it
+        shown.0.push(rest.0.remove(0));
+    }
+
+    let end = page.offset + shown.0.len();
+    let mut out = format!(
+        "result set `{set}`: {total} results, showing {} to {end}\n{}",
+        page.offset + 1,
+        serde_json::to_string(&shown.0)?
+    );
+    if end < total {
+        out.push_str(&format!("\n{} more results, pass offset {end} to see them", total - end));
+    }
+    Ok(out)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::env;
-    use std::path::PathBuf;
-    use std::str::FromStr;
-    use env_logger::Env;
-    use openai_utils::api_key;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use serde_json::json;
     use crate::ai::blacklist;
-    use crate::ai::search::find_file;
-    use crate::config::CONFIG;
+    use crate::ai::budget::{SearchConfig, StopReason};
+    use crate::ai::cassette::Cassette;
+    use crate::ai::provider::Provider;
+    use crate::ai::scripted::Scripted;
+    use crate::ai::search::{find_file, render_page, PageArgs, NO_TOOL_CALLS};
+    use crate::config::{Config, CONFIG};
+    use crate::ctags::backend::TagBackendKind;
+    use crate::ctags::{Ctag, CtagsOutput};
+    use crate::tiktoken::{bpe_for, TokensLen};
+
+    /// The tests share the global config, they must not run at the same time.
+    static CONFIG_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// A turn of the finder calling every one of `calls` at once.
+    fn tool_calls(calls: &[(&str, serde_json::Value)]) -> serde_json::Value {
+        let calls = calls
+            .iter()
+            .enumerate()
+            .map(|(i, (name, arguments))| {
+                json!({ "id": format!("call_{i}"), "type": "function", "function": { "name": name, "arguments": arguments.to_string() } })
+            })
+            .collect::<Vec<_>>();
+        json!({ "role": "assistant", "tool_calls": calls })
+    }
+
+    fn tool_call(name: &str, arguments: serde_json::Value) -> serde_json::Value {
+        tool_calls(&[(name, arguments)])
+    }
+
+    fn mock_project() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::create_dir_all(dir.path().join("target/debug")).unwrap();
+        fs::write(dir.path().join("Cargo.toml"), "[package]\nname = \"mock\"\n").unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "// DEV: entry point\npub fn open() {}\n").unwrap();
+        fs::write(dir.path().join("src/util.rs"), "pub fn close() {}\n").unwrap();
+        dir
+    }
 
-    fn init() {
-        env_logger::Builder::from_env(Env::default().default_filter_or("trace")).init();
-        dotenv::dotenv().unwrap();
-        api_key(env::var("OPENAI_API_KEY").unwrap());
-        (*CONFIG.write().unwrap()).project_dir = Some(PathBuf::from_str("mock_project").unwrap());
+    fn configure(project_dir: &Path, search: SearchConfig) {
+        *CONFIG.write().unwrap() = Config {
+            project_dir: Some(project_dir.to_path_buf()),
+            tag_backend: Some(TagBackendKind::TreeSitter),
+            search,
+            ..Default::default()
+        };
+    }
+
+    async fn search(provider: &dyn Provider) -> Vec<(PathBuf, Vec<String>)> {
+        let blacklist = blacklist(provider).await.unwrap();
+        assert_eq!(blacklist, vec![PathBuf::from("target")]);
+
+        let outcome = find_file(provider, "Has a tag of kind devgpt", blacklist).await.unwrap();
+        assert_eq!(outcome.reason, StopReason::Answered);
+        outcome
+            .found
+            .unwrap()
+            .into_iter()
+            .map(|found| (found.path, found.tags.into_iter().filter_map(|t| t.name).collect()))
+            .collect()
     }
 
     #[tokio::test]
     async fn test_searching() {
-        init();
-        
-        let blacklist = blacklist().await.unwrap();
-        let res = find_file("Has a tag of kind devgpt", blacklist).await;
+        let _lock = CONFIG_LOCK.lock().await;
+        let cassette = tempfile::NamedTempFile::new().unwrap();
+
+        let recorded = mock_project();
+        configure(recorded.path(), SearchConfig::default());
+        let lib = recorded.path().join("src/lib.rs");
+        let mut answer = tool_call("stop_searching", json!({ "results": [{
+            "path": lib, "from": 1, "confidence": 0.9, "justification": "has the DEV comment"
+        }] }));
+        answer["content"] = json!("found it");
+        let api = Scripted::new([
+            tool_call("find_kind", json!({ "kind": "devgpt" })),
+            answer,
+        ]);
+        let recorder = Cassette::record(cassette.path(), Box::new(api), Some(recorded.path()));
+        let found = search(&recorder).await;
+        assert_eq!(found, vec![(lib, vec!["entry point".to_string()])]);
+
+        // a fresh checkout somewhere else replays the session without the api
+        let replayed = mock_project();
+        configure(replayed.path(), SearchConfig::default());
+        let player = Cassette::replay(cassette.path(), Some(replayed.path())).unwrap();
+        let found = search(&player).await;
+        assert_eq!(found, vec![(replayed.path().join("src/lib.rs"), vec!["entry point".to_string()])]);
+
+        // a different question isn't in the cassette
+        let player = Cassette::replay(cassette.path(), Some(replayed.path())).unwrap();
+        assert!(find_file(&player, "something else", vec![]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn limits_end_the_search_with_the_best_candidates() {
+        let _lock = CONFIG_LOCK.lock().await;
+        let project = mock_project();
+        configure(project.path(), SearchConfig { max_rounds: Some(2), ..Default::default() });
+        let find_fn = tool_call("find_kind", json!({ "kind": "function" }));
+        let api = Scripted::new([find_fn.clone(), find_fn]);
+
+        let outcome = find_file(&api, "never stops", vec![]).await.unwrap();
+
+        assert_eq!(outcome.reason, StopReason::MaxRounds(2));
+        let mut paths = outcome.found.unwrap().into_iter().map(|f| f.path).collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, vec![project.path().join("src/lib.rs"), project.path().join("src/util.rs")]);
+    }
+
+    #[tokio::test]
+    async fn combines_named_result_sets() {
+        let _lock = CONFIG_LOCK.lock().await;
+        let project = mock_project();
+        configure(project.path(), SearchConfig::default());
+        let api = Scripted::new([
+            tool_call("find_kind", json!({ "kind": "function", "save_as": "functions" })),
+            tool_call("find_path", json!({ "path": "src/lib.rs", "save_as": "lib" })),
+            tool_call("intersect", json!({ "sets": ["functions", "lib"] })),
+            tool_call("union", json!({ "sets": ["functions", "fns"] })),
+            tool_call("stop_searching", json!({ "results": [
+                { "path": "src/util.rs", "confidence": 0.2, "justification": "no functions" },
+                { "path": "src/lib.rs", "confidence": 0.8, "justification": "defines open" },
+            ] })),
+        ]);
+
+        let outcome = find_file(&api, "functions in the lib", vec![]).await.unwrap();
+
+        assert_eq!(
+            api.last_message(),
+            "there is no result set named `fns`, the sets are: `functions`, `lib`, `result`"
+        );
+        let found = outcome.found.unwrap();
+        assert_eq!(found.iter().map(|f| f.confidence.unwrap()).collect::<Vec<_>>(), [0.8, 0.2]);
+        assert_eq!(found[0].justification.as_deref(), Some("defines open"));
+        assert_eq!(found[0].snippet.as_deref(), Some("pub fn open() {}\n"));
+        assert_eq!(found[0].tags.iter().filter_map(|t| t.name.as_deref()).collect::<Vec<_>>(), ["open"]);
+    }
+
+    #[tokio::test]
+    async fn asks_for_a_tool_call_after_a_plain_reply() {
+        let _lock = CONFIG_LOCK.lock().await;
+        let project = mock_project();
+        configure(project.path(), SearchConfig::default());
+        let api = Scripted::new([
+            json!({ "role": "assistant", "content": "I would look at lib.rs" }),
+            tool_call("stop_searching", json!({ "results": null })),
+        ]);
+
+        let outcome = find_file(&api, "the entry point", vec![]).await.unwrap();
+
+        assert_eq!(outcome.reason, StopReason::Answered);
+        assert_eq!(api.requests()[1].messages.last().unwrap().content.as_deref(), Some(NO_TOOL_CALLS));
+    }
+
+    #[tokio::test]
+    async fn runs_the_tool_calls_of_a_turn_together() {
+        let _lock = CONFIG_LOCK.lock().await;
+        let project = mock_project();
+        configure(project.path(), SearchConfig::default());
+        let api = Scripted::new([
+            tool_calls(&[
+                ("find_name", json!({ "name": "open", "save_as": "named" })),
+                ("find_kind", json!({ "kind": "function", "save_as": "functions" })),
+                ("find_kind", json!({ "kind": 3 })),
+            ]),
+            tool_call("intersect", json!({ "sets": ["named", "functions"] })),
+            tool_call("stop_searching", json!({ "results": null })),
+        ]);
+
+        let outcome = find_file(&api, "the open function", vec![]).await.unwrap();
+        assert_eq!(outcome.reason, StopReason::Answered);
+
+        let requests = api.requests();
+        let tools = requests[0].tools.as_ref().unwrap();
+        assert!(tools.iter().any(|t| t.kind == "function" && t.function.name == "find_name"));
+
+        let replies = requests[1].messages.iter().filter(|m| m.role == "tool").collect::<Vec<_>>();
+        let ids = replies.iter().filter_map(|m| m.tool_call_id.as_deref()).collect::<Vec<_>>();
+        assert_eq!(ids, ["call_0", "call_1", "call_2"]);
+        assert!(replies[0].content.as_deref().unwrap().starts_with("result set `named`: 1 results"));
+        assert!(replies[1].content.as_deref().unwrap().starts_with("result set `functions`: 2 results"));
+        assert!(replies[2].content.as_deref().unwrap().starts_with("invalid arguments for find_kind: `kind` must be a string, not 3\n"));
+
+        let intersected = requests[2].messages.last().unwrap().content.clone().unwrap();
+        assert!(intersected.starts_with("result set `result`: 1 results"), "{intersected}");
+    }
+
+    #[tokio::test]
+    async fn tells_the_finder_the_layout_up_front() {
+        let _lock = CONFIG_LOCK.lock().await;
+        let project = mock_project();
+        configure(project.path(), SearchConfig { overview: true, ..Default::default() });
+        let api = Scripted::new([tool_call("stop_searching", json!({ "results": null }))]);
+
+        find_file(&api, "the entry point", vec![PathBuf::from("target")]).await.unwrap();
+
+        let requests = api.requests();
+        let question = requests[0].messages.last().unwrap().content.clone().unwrap();
+        assert_eq!(
+            question,
+            "the entry point\n\nThe directories of the project:\n./ 3 files, Rust 2, TOML 1\n  src/ 2 files, Rust 2\n"
+        );
+    }
+
+    fn tag(name: &str) -> Ctag {
+        serde_json::from_value(json!({ "_type": "tag", "name": name, "path": "src/lib.rs", "kind": "function" })).unwrap()
+    }
+
+    #[test]
+    fn pages_through_results() {
+        let bpe = bpe_for("gpt-4");
+        let result = CtagsOutput((0..5).map(|i| tag(&format!("f{i}"))).collect());
+
+        let page = render_page(&result, "result", PageArgs { offset: 1, limit: Some(2) }, &bpe, 1000).unwrap();
+        assert!(page.starts_with("result set `result`: 5 results, showing 2 to 3\n"));
+        assert!(page.contains("\"f1\"") && page.contains("\"f2\"") && !page.contains("\"f3\""));
+        assert!(page.ends_with("2 more results, pass offset 3 to see them"));
+
+        let last = render_page(&result, "result", PageArgs { offset: 4, limit: None }, &bpe, 1000).unwrap();
+        assert!(!last.contains("more results"));
+
+        let empty_limit = render_page(&result, "result", PageArgs { offset: 0, limit: Some(0) }, &bpe, 1000).unwrap();
+        assert!(empty_limit.starts_with("result set `result`: 5 results, showing 1 to 1\n"));
+
+        let past_the_end = render_page(&result, "result", PageArgs { offset: 9, limit: None }, &bpe, 1000).unwrap();
+        assert_eq!(past_the_end, "result set `result`: 5 results, none after offset 9");
+    }
+
+    #[test]
+    fn caps_pages_to_the_token_budget() {
+        let bpe = bpe_for("gpt-4");
+        let result = CtagsOutput((0..5).map(|i| tag(&format!("f{i}"))).collect());
+        let one_tag = result.0[0].token_len(&bpe);
+
+        let page = render_page(&result, "result", PageArgs::default(), &bpe, one_tag * 2).unwrap();
+        assert!(page.starts_with("result set `result`: 5 results, showing 1 to 2\n"));
+        assert!(page.ends_with("3 more results, pass offset 2 to see them"));
 
-        dbg!(res);
+        // a tag over the budget on its own still gets shown
+        let page = render_page(&result, "result", PageArgs::default(), &bpe, 1).unwrap();
+        assert!(page.ends_with("4 more results, pass offset 1 to see them"));
     }
 }
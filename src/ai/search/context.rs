@@ -0,0 +1,121 @@
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use serde_derive::Serialize;
+use tiktoken_rs::CoreBPE;
+use crate::ai::search::{resolve, ToolError};
+use crate::tiktoken::TokensLen;
+
+/// Code of a file the finder asked to read. `lines` are 1-based and the end is exclusive.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Context {
+    pub file: PathBuf,
+    pub lines: Range<u64>,
+    pub code: String,
+}
+
+/// A [`Context`] cut short to fit in the token budget, with the number of lines left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Read {
+    pub context: Context,
+    pub lines_left: u64,
+}
+
+impl Read {
+    /// Renders the read for the finder, telling it where to go on when the code was cut short.
+    pub fn render(&self) -> anyhow::Result<String> {
+        let mut out = serde_json::to_string(&self.context)?;
+        if self.lines_left > 0 {
+            out.push_str(&format!(
+                "\ntruncated, {} more lines, read from line {} to see them",
+                self.lines_left, self.context.lines.end
+            ));
+        }
+        Ok(out)
+    }
+}
+
+/// Reads lines `from..=to` of `path`, which has to be inside `project_dir` and not blacklisted.
+/// Lines are taken until the next one would go over `max_tokens`, but at least one is read.
+pub fn read_file(
+    project_dir: &Path,
+    blacklist: &[&Path],
+    path: &Path,
+    from: Option<u64>,
+    to: Option<u64>,
+    bpe: &CoreBPE,
+    max_tokens: usize,
+) -> Result<Read, ToolError> {
+    let unreadable = |reason: String| ToolError::Unreadable { path: path.to_path_buf(), reason };
+    let file = resolve(project_dir, blacklist, path)?;
+
+    let contents = fs::read_to_string(&file).map_err(|e| unreadable(e.to_string()))?;
+    let lines = contents.lines().collect::<Vec<_>>();
+    let from = from.unwrap_or(1).max(1);
+    let to = to.unwrap_or(lines.len() as u64).min(lines.len() as u64);
+    if from > to {
+        return Err(unreadable(format!("it has {} lines, there is nothing from line {from} to {to}", lines.len())));
+    }
+
+    let mut code = String::new();
+    let mut tokens = 0;
+    let mut end = from;
+    for line in &lines[from as usize - 1..to as usize] {
+        let next = format!("{line}\n");
+        tokens += next.token_len(bpe);
+        if end > from && tokens > max_tokens {
+            break;
+        }
+        code.push_str(&next);
+        end += 1;
+    }
+
+    Ok(Read {
+        context: Context { file, lines: from..end, code },
+        lines_left: to + 1 - end,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use crate::ai::search::context::read_file;
+    use crate::ai::search::ToolError;
+    use crate::tiktoken::{bpe_for, TokensLen};
+
+    #[test]
+    fn reads_line_ranges_within_the_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("lib.rs");
+        fs::write(&file, "fn a() {}\nfn b() {}\nfn c() {}\nfn d() {}\n").unwrap();
+        let bpe = bpe_for("gpt-4");
+
+        let read = read_file(dir.path(), &[], Path::new("lib.rs"), Some(2), Some(3), &bpe, 1000).unwrap();
+        assert_eq!(read.context.code, "fn b() {}\nfn c() {}\n");
+        assert_eq!(read.context.lines, 2..4);
+        assert_eq!(read.lines_left, 0);
+
+        let two_lines = "fn a() {}\n".to_string().token_len(&bpe) * 2;
+        let read = read_file(dir.path(), &[], &file.canonicalize().unwrap(), None, None, &bpe, two_lines).unwrap();
+        assert_eq!(read.context.lines, 1..3);
+        assert_eq!(read.lines_left, 2);
+        assert!(read.render().unwrap().ends_with("truncated, 2 more lines, read from line 3 to see them"));
+    }
+
+    #[test]
+    fn stays_inside_the_project() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("project/target")).unwrap();
+        fs::write(dir.path().join("secret"), "key").unwrap();
+        fs::write(dir.path().join("project/target/out.rs"), "fn a() {}").unwrap();
+        let project = dir.path().join("project");
+        let bpe = bpe_for("gpt-4");
+
+        let outside = read_file(&project, &[], Path::new("../secret"), None, None, &bpe, 1000).unwrap_err();
+        assert!(matches!(outside, ToolError::Unreadable { reason, .. } if reason == "it is outside of the project"));
+
+        let blacklisted = read_file(&project, &[Path::new("target")], Path::new("target/out.rs"), None, None, &bpe, 1000);
+        assert!(matches!(blacklisted, Err(ToolError::Unreadable { reason, .. }) if reason == "it is blacklisted"));
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use log::{debug, trace};
+use regex::RegexBuilder;
+use walkdir::WalkDir;
+use crate::ai::search::ToolError;
+use crate::ctags::{is_excluded_path, pattern, Ctag};
+use crate::gitignore::GitIgnores;
+
+/// The walk stops after this many matches, the finder should search for something more specific.
+const MAX_MATCHES: usize = 1000;
+/// Bytes looked at for a NUL byte to tell binary files apart, the same amount git uses.
+const BINARY_SNIFF_LEN: usize = 8000;
+/// Files larger than this are skipped, they are generated or data rather than code.
+const MAX_FILE_LEN: u64 = 1024 * 1024;
+
+/// A line of a file that matched a content search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrepMatch {
+    pub file: PathBuf,
+    pub line: u32,
+    /// The text that matched.
+    pub text: String,
+    /// The whole line as it is in the file, so the pattern of its tag matches it.
+    pub snippet: String,
+}
+
+impl From<GrepMatch> for Ctag {
+    /// Matches become tags of kind `match`, so they go into result sets like any other tag.
+    fn from(m: GrepMatch) -> Self {
+        Ctag {
+            _type: "tag".to_string(),
+            name: Some(m.text),
+            path: Some(m.file),
+            pattern: Some(pattern(&m.snippet)),
+            kind: Some("match".to_string()),
+            line: Some(m.line),
+            ..Default::default()
+        }
+    }
+}
+
+/// Searches the text files of `project_dir` for `query`, a regex or, when `literal`, plain text.
+/// Blacklisted and git ignored paths are skipped, and so are files that aren't UTF-8 text or are
+/// larger than [`MAX_FILE_LEN`].
+pub fn grep(
+    project_dir: &Path,
+    blacklist: &[&Path],
+    query: &str,
+    literal: bool,
+    ignore_case: bool,
+) -> Result<Vec<GrepMatch>, ToolError> {
+    let query = if literal { regex::escape(query) } else { query.to_string() };
+    let regex = RegexBuilder::new(&query)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| ToolError::InvalidPattern(e.to_string()))?;
+
+    let mut ignores = GitIgnores::new(project_dir);
+    let walker = WalkDir::new(project_dir).sort_by_file_name().into_iter().filter_entry(|entry| {
+        let relative = entry.path().strip_prefix(project_dir).unwrap_or(entry.path());
+        entry.depth() == 0
+            || !(is_excluded_path(relative, blacklist) || ignores.is_ignored(entry.path(), entry.file_type().is_dir()))
+    });
+
+    let mut matches = vec![];
+    for entry in walker.filter_map(Result::ok).filter(|e| e.file_type().is_file()) {
+        if entry.metadata().map_or(true, |meta| meta.len() > MAX_FILE_LEN) {
+            trace!("skipping large file {}", entry.path().display());
+            continue;
+        }
+        let Some(contents) = read_text(entry.path()) else {
+            trace!("skipping binary file {}", entry.path().display());
+            continue;
+        };
+
+        for (i, line) in contents.lines().enumerate() {
+            if let Some(found) = regex.find(line) {
+                matches.push(GrepMatch {
+                    file: entry.path().to_path_buf(),
+                    line: i as u32 + 1,
+                    text: found.as_str().to_string(),
+                    snippet: line.to_string(),
+                });
+                if matches.len() == MAX_MATCHES {
+                    debug!("stopped searching at {MAX_MATCHES} matches");
+                    return Ok(matches);
+                }
+            }
+        }
+    }
+
+    Ok(matches)
+}
+
+fn read_text(path: &Path) -> Option<String> {
+    let bytes = fs::read(path).ok()?;
+    if bytes.iter().take(BINARY_SNIFF_LEN).any(|b| *b == 0) {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use crate::ai::search::grep::{grep, MAX_FILE_LEN};
+    use crate::ai::search::ToolError;
+    use crate::ctags::Ctag;
+
+    #[test]
+    fn searches_text_files_outside_of_the_blacklist() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::write(root.join(".gitignore"), "logs/\n").unwrap();
+        fs::write(root.join("src/net.rs"), "fn connect() {\n    error!(\"Connection refused: {e}\");\n}\n").unwrap();
+        fs::write(root.join("target/net.rs"), "connection refused").unwrap();
+        fs::write(root.join("logs/today.txt"), "connection refused").unwrap();
+        fs::write(root.join("src/blob.bin"), b"connection refused\0").unwrap();
+
+        let matches = grep(root, &[Path::new("target")], "connection refused", true, true).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].file, root.join("src/net.rs"));
+        assert_eq!(matches[0].line, 2);
+        assert_eq!(matches[0].text, "Connection refused");
+        assert_eq!(matches[0].snippet, "    error!(\"Connection refused: {e}\");");
+        let tag = Ctag::from(matches[0].clone());
+        assert_eq!(tag.pattern.as_deref(), Some("/^    error!(\"Connection refused: {e}\");$/"));
+
+        assert!(grep(root, &[Path::new("target")], "connection refused", true, false).unwrap().is_empty());
+        assert_eq!(grep(root, &[], r"fn \w+\(", false, false).unwrap()[0].text, "fn connect(");
+        assert!(matches!(grep(root, &[], "(", false, false), Err(ToolError::InvalidPattern(_))));
+    }
+
+    #[test]
+    fn skips_large_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("small.txt"), "needle\n").unwrap();
+        fs::write(root.join("large.txt"), "needle\n".repeat(MAX_FILE_LEN as usize / 7 + 1)).unwrap();
+
+        let matches = grep(root, &[], "needle", true, false).unwrap();
+        assert_eq!(matches.iter().map(|m| m.file.clone()).collect::<Vec<_>>(), [root.join("small.txt")]);
+    }
+}
@@ -0,0 +1,156 @@
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use serde_derive::Serialize;
+use tiktoken_rs::CoreBPE;
+use crate::ai::search::context::read_file;
+use crate::ctags::{normalize, Ctag};
+
+/// Lines of code shown of a result at most.
+const SNIPPET_LINES: u32 = 12;
+/// Tokens a snippet may take up, long lines are cut off at the line that goes over.
+const SNIPPET_TOKENS: usize = 400;
+
+/// Lines of a file, 1-based and inclusive on both ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LineRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+/// A place in the code the finder settled on, or a candidate of a search that hit a limit, which
+/// has no confidence or justification.
+#[derive(Debug, Clone, Serialize)]
+pub struct FoundFile {
+    pub path: PathBuf,
+    pub lines: Option<LineRange>,
+    /// Tags of the last search results that overlap the lines, or in the file without lines.
+    pub tags: Vec<Ctag>,
+    pub snippet: Option<String>,
+    /// How sure the finder is, from 0 to 1.
+    pub confidence: Option<f64>,
+    /// Why the finder thinks the code satisfies the question, in one line.
+    pub justification: Option<String>,
+}
+
+impl FoundFile {
+    /// A result at `path`, with the tags of `result` in `lines`. Without lines, the lines are the
+    /// ones the tags span. Tags count as in the file however its path is written, like with `..`
+    /// or through a symlink.
+    pub fn new(path: PathBuf, lines: Option<LineRange>, result: &[Ctag]) -> Self {
+        let normal = normalize(&path, None);
+        let canonical = path.canonicalize().ok();
+        let same_file = |tag: &Path| {
+            normalize(tag, None) == normal || canonical.is_some() && tag.canonicalize().ok() == canonical
+        };
+        let tags = result
+            .iter()
+            .filter(|t| t.path.as_deref().is_some_and(same_file))
+            .filter(|t| lines.is_none_or(|lines| t.spans(lines.start, lines.end)))
+            .cloned()
+            .collect::<Vec<_>>();
+        let lines = lines.or_else(|| {
+            let start = tags.iter().filter_map(|t| t.line).min()?;
+            let end = tags.iter().filter_map(|t| t.end.or(t.line)).max()?;
+            Some(LineRange { start, end })
+        });
+
+        Self { path, lines, tags, snippet: None, confidence: None, justification: None }
+    }
+}
+
+/// Files of `tags` in the order they first show up, each with all of its tags.
+pub fn candidates(tags: &[Ctag]) -> Vec<FoundFile> {
+    let mut paths: Vec<&PathBuf> = vec![];
+    for path in tags.iter().filter_map(|t| t.path.as_ref()) {
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths.into_iter().map(|path| FoundFile::new(path.clone(), None, tags)).collect()
+}
+
+/// Puts the results the finder is most sure about first and reads the start of the code of each.
+/// Snippets are left out of results that can't be read, like ones outside of the project.
+pub fn finish(found: &mut [FoundFile], project_dir: &Path, blacklist: &[&Path], bpe: &CoreBPE) {
+    found.sort_by(|a, b| match (a.confidence, b.confidence) {
+        (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+
+    for result in found {
+        let (from, to) = match result.lines {
+            Some(lines) => (lines.start, lines.end.min(lines.start + SNIPPET_LINES - 1)),
+            None => (1, SNIPPET_LINES),
+        };
+        let read = read_file(project_dir, blacklist, &result.path, Some(from as u64), Some(to as u64), bpe, SNIPPET_TOKENS);
+        result.snippet = read.ok().map(|read| read.context.code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use serde_json::json;
+    use crate::ai::search::results::{candidates, finish, FoundFile, LineRange};
+    use crate::ctags::Ctag;
+    use crate::tiktoken::bpe_for;
+
+    fn tag(name: &str, path: &str, line: u32) -> Ctag {
+        serde_json::from_value(json!({ "_type": "tag", "name": name, "path": path, "line": line })).unwrap()
+    }
+
+    #[test]
+    fn keeps_the_tags_in_range() {
+        let tags = [tag("a", "lib.rs", 1), tag("b", "lib.rs", 5), tag("c", "lib.rs", 9), tag("d", "main.rs", 2)];
+
+        let found = FoundFile::new(PathBuf::from("lib.rs"), Some(LineRange { start: 4, end: 9 }), &tags);
+        assert_eq!(found.tags, [tags[1].clone(), tags[2].clone()]);
+        let found = FoundFile::new(PathBuf::from("./src/../lib.rs"), Some(LineRange { start: 4, end: 9 }), &tags);
+        assert_eq!(found.tags, [tags[1].clone(), tags[2].clone()]);
+
+        let mut spanning = tag("a", "lib.rs", 1);
+        spanning.end = Some(4);
+        let found = FoundFile::new(PathBuf::from("lib.rs"), Some(LineRange { start: 3, end: 3 }), &[spanning.clone()]);
+        assert_eq!(found.tags, [spanning]);
+
+        let found = candidates(&tags);
+        assert_eq!(found.iter().map(|f| f.path.to_str().unwrap()).collect::<Vec<_>>(), ["lib.rs", "main.rs"]);
+        assert_eq!(found[0].lines, Some(LineRange { start: 1, end: 9 }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn finds_the_tags_of_a_symlinked_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = dir.path().join("lib.rs");
+        fs::write(&lib, "fn a() {}\n").unwrap();
+        std::os::unix::fs::symlink(&lib, dir.path().join("link.rs")).unwrap();
+        let tags = [tag("a", lib.to_str().unwrap(), 1)];
+
+        let found = FoundFile::new(dir.path().join("link.rs"), None, &tags);
+        assert_eq!(found.tags, tags);
+        assert_eq!(found.lines, Some(LineRange { start: 1, end: 1 }));
+    }
+
+    #[test]
+    fn ranks_and_reads_snippets() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("lib.rs"), "fn a() {}\nfn b() {}\nfn c() {}\n").unwrap();
+        let mut unsure = FoundFile::new(PathBuf::from("lib.rs"), Some(LineRange { start: 2, end: 3 }), &[]);
+        unsure.confidence = Some(0.4);
+        let mut sure = FoundFile::new(PathBuf::from("lib.rs"), Some(LineRange { start: 1, end: 1 }), &[]);
+        sure.confidence = Some(0.9);
+        let gone = FoundFile::new(PathBuf::from("gone.rs"), None, &[]);
+        let mut found = [gone, unsure, sure];
+
+        finish(&mut found, dir.path(), &[], &bpe_for("gpt-4"));
+
+        assert_eq!(found.iter().map(|f| f.confidence).collect::<Vec<_>>(), [Some(0.9), Some(0.4), None]);
+        assert_eq!(found[0].snippet.as_deref(), Some("fn a() {}\n"));
+        assert_eq!(found[1].snippet.as_deref(), Some("fn b() {}\nfn c() {}\n"));
+        assert_eq!(found[2].snippet, None);
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::{BTreeMap, HashSet};
+use itertools::Itertools;
+use crate::ai::search::ToolError;
+use crate::ctags::{Ctag, CtagsOutput};
+
+/// Name of the set a tool saves to when the finder doesn't name one.
+pub const DEFAULT_SET: &str = "result";
+
+/// The result sets of a search by name, every tool saves its results to one of them.
+#[derive(Debug, Default, Clone)]
+pub struct ResultSets {
+    sets: BTreeMap<String, CtagsOutput>,
+    last: Option<String>,
+}
+
+impl ResultSets {
+    pub fn get(&self, name: &str) -> Result<&CtagsOutput, ToolError> {
+        self.sets.get(name).ok_or_else(|| ToolError::UnknownSet {
+            name: name.to_string(),
+            known: if self.sets.is_empty() {
+                "none yet".to_string()
+            } else {
+                self.sets.keys().map(|k| format!("`{k}`")).join(", ")
+            },
+        })
+    }
+
+    /// Saves `tags` under `name`, or the default set, and returns the name it used.
+    pub fn save(&mut self, name: Option<String>, tags: Vec<Ctag>) -> String {
+        let name = name.unwrap_or_else(|| DEFAULT_SET.to_string());
+        self.sets.insert(name.clone(), CtagsOutput(tags));
+        self.last = Some(name.clone());
+        name
+    }
+
+    /// The tags of the set saved to last, what the finder is looking at right now.
+    pub fn last(&self) -> &[Ctag] {
+        self.last.as_ref().and_then(|name| self.sets.get(name)).map_or(&[], |set| &set.0)
+    }
+
+    /// Tags in every one of `names`, in the order of the first.
+    pub fn intersect(&self, names: &[String]) -> Result<Vec<Ctag>, ToolError> {
+        let (first, rest) = self.split("intersect", names)?;
+        let rest = rest.iter().map(|set| set.0.iter().collect::<HashSet<_>>()).collect::<Vec<_>>();
+        Ok(first.0.iter().filter(|tag| rest.iter().all(|set| set.contains(tag))).unique().cloned().collect())
+    }
+
+    /// Tags in any of `names`, each once, in the order they first show up.
+    pub fn union(&self, names: &[String]) -> Result<Vec<Ctag>, ToolError> {
+        let (first, rest) = self.split("union", names)?;
+        Ok(first.0.iter().chain(rest.iter().flat_map(|set| set.0.iter())).unique().cloned().collect())
+    }
+
+    /// Tags of `from` in none of `remove`.
+    pub fn subtract(&self, from: &str, remove: &[String]) -> Result<Vec<Ctag>, ToolError> {
+        let from = self.get(from)?;
+        let remove = remove.iter().map(|name| self.get(name)).collect::<Result<Vec<_>, _>>()?;
+        let remove = remove.iter().flat_map(|set| set.0.iter()).collect::<HashSet<_>>();
+        Ok(from.0.iter().filter(|tag| !remove.contains(tag)).cloned().collect())
+    }
+
+    fn split(&self, op: &'static str, names: &[String]) -> Result<(&CtagsOutput, Vec<&CtagsOutput>), ToolError> {
+        let (first, rest) = names.split_first().ok_or(ToolError::NoSets(op))?;
+        Ok((self.get(first)?, rest.iter().map(|name| self.get(name)).collect::<Result<_, _>>()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::ai::search::sets::ResultSets;
+    use crate::ai::search::ToolError;
+    use crate::ctags::Ctag;
+
+    fn tags(names: &[&str]) -> Vec<Ctag> {
+        names
+            .iter()
+            .map(|name| serde_json::from_value(json!({ "_type": "tag", "name": name, "path": "src/lib.rs" })).unwrap())
+            .collect()
+    }
+
+    fn names(tags: Vec<Ctag>) -> Vec<String> {
+        tags.into_iter().filter_map(|t| t.name).collect()
+    }
+
+    #[test]
+    fn combines_sets() {
+        let mut sets = ResultSets::default();
+        sets.save(Some("a".to_string()), tags(&["parse", "open", "close"]));
+        sets.save(Some("b".to_string()), tags(&["close", "parse", "read"]));
+        let ab = ["a".to_string(), "b".to_string()];
+
+        assert_eq!(names(sets.intersect(&ab).unwrap()), ["parse", "close"]);
+        assert_eq!(names(sets.union(&ab).unwrap()), ["parse", "open", "close", "read"]);
+        assert_eq!(names(sets.subtract("a", &ab[1..]).unwrap()), ["open"]);
+        assert_eq!(names(sets.last().to_vec()), ["close", "parse", "read"]);
+    }
+
+    #[test]
+    fn names_the_known_sets_on_a_typo() {
+        let mut sets = ResultSets::default();
+        assert!(matches!(sets.get("x"), Err(ToolError::UnknownSet { known, .. }) if known == "none yet"));
+
+        sets.save(None, vec![]);
+        sets.save(Some("functions".to_string()), vec![]);
+        let error = sets.union(&["functions".to_string(), "fns".to_string()]).unwrap_err();
+        assert_eq!(error.to_string(), "there is no result set named `fns`, the sets are: `functions`, `result`");
+        assert!(matches!(sets.intersect(&[]), Err(ToolError::NoSets("intersect"))));
+    }
+}
@@ -0,0 +1,449 @@
+use std::path::{Path, PathBuf};
+use itertools::Itertools;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use crate::as_paths;
+use crate::ai::search::context::read_file;
+use crate::ai::search::grep::grep;
+use crate::ai::search::results::{FoundFile, LineRange};
+use crate::ai::search::tree::list_dir;
+use crate::ai::search::{path_pattern, Found, Outcome, Turn, DEFAULT_LIST_DEPTH};
+use crate::ai::tool::{Project, Registry, Tool};
+use crate::ctags::{best_first, Ctag, NameMatcher, PathPattern};
+
+/// Every tool of the finder, in the order it is told about them.
+pub fn finder_tools() -> Registry<Turn, Outcome> {
+    let mut tools = Registry::default();
+    tools
+        .register(FindName)
+        .register(FindPath)
+        .register(FindKind)
+        .register(FindLineRange)
+        .register(Grep)
+        .register(Narrow)
+        .register(Intersect)
+        .register(Union)
+        .register(Subtract)
+        .register(ReadFile)
+        .register(ListDir)
+        .register(StopSearching);
+    tools
+}
+
+// the find tools search the whole pool of tags, `narrow` and the set operations only look at
+// result sets saved earlier
+
+struct FindName;
+
+impl Tool<Turn> for FindName {
+    type Args = FindNameArgs;
+    type Output = Found;
+
+    fn name(&self) -> &'static str {
+        "find_name"
+    }
+
+    fn execute(&self, args: FindNameArgs, turn: &Turn) -> anyhow::Result<Found> {
+        let matcher = NameMatcher::new(&args.name);
+        let tags = turn
+            .tags
+            .0
+            .iter()
+            .filter_map(|t| Some((t.name_score(&matcher)?, t)))
+            .sorted_by(|a, b| best_first(a.0, b.0))
+            .map(|(_, t)| t.clone())
+            .collect();
+        Ok(Found { tags, save_as: args.save_as, page: args.page })
+    }
+}
+
+struct FindPath;
+
+impl Tool<Turn> for FindPath {
+    type Args = FindPathArgs;
+    type Output = Found;
+
+    fn name(&self) -> &'static str {
+        "find_path"
+    }
+
+    fn execute(&self, args: FindPathArgs, turn: &Turn) -> anyhow::Result<Found> {
+        let pattern = path_pattern(&args.path, &turn.project_dir)?;
+        let tags = turn.tags.0.iter().filter(|t| t.path_matches(&pattern)).cloned().collect();
+        Ok(Found { tags, save_as: args.save_as, page: args.page })
+    }
+}
+
+struct FindKind;
+
+impl Tool<Turn> for FindKind {
+    type Args = FindKindArgs;
+    type Output = Found;
+
+    fn name(&self) -> &'static str {
+        "find_kind"
+    }
+
+    fn execute(&self, args: FindKindArgs, turn: &Turn) -> anyhow::Result<Found> {
+        let tags = turn.tags.0.iter().filter(|t| t.kind_is(&args.kind)).cloned().collect();
+        Ok(Found { tags, save_as: args.save_as, page: args.page })
+    }
+}
+
+struct FindLineRange;
+
+impl Tool<Turn> for FindLineRange {
+    type Args = FindLineRangeArgs;
+    type Output = Found;
+
+    fn name(&self) -> &'static str {
+        "find_line_range"
+    }
+
+    fn execute(&self, args: FindLineRangeArgs, turn: &Turn) -> anyhow::Result<Found> {
+        let tags = turn.tags.0.iter().filter(|t| t.spans(args.from, args.to)).cloned().collect();
+        Ok(Found { tags, save_as: args.save_as, page: args.page })
+    }
+}
+
+struct Grep;
+
+impl<C: Project> Tool<C> for Grep {
+    type Args = GrepArgs;
+    type Output = Found;
+
+    fn name(&self) -> &'static str {
+        "grep"
+    }
+
+    fn execute(&self, args: GrepArgs, project: &C) -> anyhow::Result<Found> {
+        let matches = grep(project.project_dir(), &as_paths(project.blacklist()), &args.pattern, args.literal, args.ignore_case)?;
+        Ok(Found { tags: matches.into_iter().map(Ctag::from).collect(), save_as: args.save_as, page: args.page })
+    }
+}
+
+struct Narrow;
+
+impl Tool<Turn> for Narrow {
+    type Args = NarrowArgs;
+    type Output = Found;
+
+    fn name(&self) -> &'static str {
+        "narrow"
+    }
+
+    fn execute(&self, args: NarrowArgs, turn: &Turn) -> anyhow::Result<Found> {
+        let name = args.name.as_deref().map(NameMatcher::new);
+        let path = args.path.as_ref().map(|path| path_pattern(path, &turn.project_dir)).transpose()?;
+        let tags = turn
+            .sets
+            .get(&args.set)?
+            .0
+            .iter()
+            .filter(|t| args.matches(t, name.as_ref(), path.as_ref()))
+            .cloned()
+            .collect();
+        Ok(Found { tags, save_as: args.save_as, page: args.page })
+    }
+}
+
+struct Intersect;
+
+impl Tool<Turn> for Intersect {
+    type Args = SetsArgs;
+    type Output = Found;
+
+    fn name(&self) -> &'static str {
+        "intersect"
+    }
+
+    fn execute(&self, args: SetsArgs, turn: &Turn) -> anyhow::Result<Found> {
+        Ok(Found { tags: turn.sets.intersect(&args.sets)?, save_as: args.save_as, page: args.page })
+    }
+}
+
+struct Union;
+
+impl Tool<Turn> for Union {
+    type Args = SetsArgs;
+    type Output = Found;
+
+    fn name(&self) -> &'static str {
+        "union"
+    }
+
+    fn execute(&self, args: SetsArgs, turn: &Turn) -> anyhow::Result<Found> {
+        Ok(Found { tags: turn.sets.union(&args.sets)?, save_as: args.save_as, page: args.page })
+    }
+}
+
+struct Subtract;
+
+impl Tool<Turn> for Subtract {
+    type Args = SubtractArgs;
+    type Output = Found;
+
+    fn name(&self) -> &'static str {
+        "subtract"
+    }
+
+    fn execute(&self, args: SubtractArgs, turn: &Turn) -> anyhow::Result<Found> {
+        Ok(Found { tags: turn.sets.subtract(&args.from, &args.remove)?, save_as: args.save_as, page: args.page })
+    }
+}
+
+struct ReadFile;
+
+impl<C: Project> Tool<C> for ReadFile {
+    type Args = ReadFileArgs;
+    type Output = String;
+
+    fn name(&self) -> &'static str {
+        "read_file"
+    }
+
+    fn execute(&self, args: ReadFileArgs, project: &C) -> anyhow::Result<String> {
+        let blacklist = as_paths(project.blacklist());
+        let read = read_file(project.project_dir(), &blacklist, &args.path, args.from, args.to, project.bpe(), project.max_result_tokens())?;
+        read.render()
+    }
+}
+
+struct ListDir;
+
+impl<C: Project> Tool<C> for ListDir {
+    type Args = ListDirArgs;
+    type Output = String;
+
+    fn name(&self) -> &'static str {
+        "list_dir"
+    }
+
+    fn execute(&self, args: ListDirArgs, project: &C) -> anyhow::Result<String> {
+        let path = args.path.unwrap_or_default();
+        let depth = args.depth.unwrap_or(DEFAULT_LIST_DEPTH);
+        let blacklist = as_paths(project.blacklist());
+        Ok(list_dir(project.project_dir(), &blacklist, &path, depth, args.files, project.bpe(), project.max_result_tokens())?)
+    }
+}
+
+struct StopSearching;
+
+impl Tool<Turn> for StopSearching {
+    type Args = StopSearchingArgs;
+    type Output = Outcome;
+
+    fn name(&self) -> &'static str {
+        "stop_searching"
+    }
+
+    fn execute(&self, args: StopSearchingArgs, _: &Turn) -> anyhow::Result<Outcome> {
+        Ok(Outcome::Stop(args.results))
+    }
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(description = "Finds the tags with a name like the given one, the closest first")]
+struct FindNameArgs {
+    #[schemars(description = "The name, in any case. Matches names containing it, with its words in another order, or with its letters in order")]
+    name: String,
+    #[serde(default)]
+    #[schemars(description = "Name to save the results under, to combine them later. Defaults to `result`")]
+    save_as: Option<String>,
+    #[serde(flatten)]
+    page: PageArgs,
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(description = "Finds the tags of the files matching a path, a relative or absolute path, a directory, the end of a path or a glob")]
+struct FindPathArgs {
+    #[schemars(description = "The path, relative to the project or absolute. Also matches the files in a directory, paths ending in it like `search.rs`, and globs like `src/**/*.rs`")]
+    path: String,
+    #[serde(default)]
+    #[schemars(description = "Name to save the results under, to combine them later. Defaults to `result`")]
+    save_as: Option<String>,
+    #[serde(flatten)]
+    page: PageArgs,
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(description = "Finds the tags of a kind, in every language")]
+struct FindKindArgs {
+    #[schemars(description = "One of function, method, type, trait, constant, variable, module, field, macro or devgpt-comment. Anything else matches the raw kinds of the parsers that contain it")]
+    kind: String,
+    #[serde(default)]
+    #[schemars(description = "Name to save the results under, to combine them later. Defaults to `result`")]
+    save_as: Option<String>,
+    #[serde(flatten)]
+    page: PageArgs,
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(description = "Finds the definitions that overlap an inclusive range of lines, like the function around a line")]
+struct FindLineRangeArgs {
+    #[schemars(description = "the start of the range")]
+    from: u32,
+    #[schemars(description = "the end of the range")]
+    to: u32,
+    #[serde(default)]
+    #[schemars(description = "Name to save the results under, to combine them later. Defaults to `result`")]
+    save_as: Option<String>,
+    #[serde(flatten)]
+    page: PageArgs,
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(description = "Searches the contents of the files in the project, each matching line becomes a tag of kind `match` named after the matched text")]
+struct GrepArgs {
+    #[schemars(description = "A regex to search for, or plain text when `literal` is set")]
+    pattern: String,
+    #[serde(default)]
+    #[schemars(description = "Search for the pattern as plain text")]
+    literal: bool,
+    #[serde(default)]
+    #[schemars(description = "Ignore the case of letters")]
+    ignore_case: bool,
+    #[serde(default)]
+    #[schemars(description = "Name to save the results under, to combine them later. Defaults to `result`")]
+    save_as: Option<String>,
+    #[serde(flatten)]
+    page: PageArgs,
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(description = "Keeps the tags of a result set that match every filter given")]
+struct NarrowArgs {
+    #[schemars(description = "The result set to narrow down")]
+    set: String,
+    #[serde(default)]
+    #[schemars(description = "Keep tags with a name like this, in any case")]
+    name: Option<String>,
+    #[serde(default)]
+    #[schemars(description = "Keep tags at this path, relative to the project or absolute. Also matches the files in a directory, paths ending in it like `search.rs`, and globs like `src/**/*.rs`")]
+    path: Option<String>,
+    #[serde(default)]
+    #[schemars(description = "Keep tags of this kind, like for find_kind")]
+    kind: Option<String>,
+    #[serde(default)]
+    #[schemars(description = "Keep tags on or after this line")]
+    from: Option<u32>,
+    #[serde(default)]
+    #[schemars(description = "Keep tags on or before this line")]
+    to: Option<u32>,
+    #[serde(default)]
+    #[schemars(description = "Name to save the results under, to combine them later. Defaults to `result`")]
+    save_as: Option<String>,
+    #[serde(flatten)]
+    page: PageArgs,
+}
+
+impl NarrowArgs {
+    fn matches(&self, tag: &Ctag, name: Option<&NameMatcher>, path: Option<&PathPattern>) -> bool {
+        name.is_none_or(|name| tag.name_score(name).is_some())
+            && path.is_none_or(|path| tag.path_matches(path))
+            && self.kind.as_ref().is_none_or(|kind| tag.kind_is(kind))
+            && self.from.is_none_or(|from| tag.line.is_some_and(|line| line >= from))
+            && self.to.is_none_or(|to| tag.line.is_some_and(|line| line <= to))
+    }
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(description = "Combines result sets")]
+struct SetsArgs {
+    #[schemars(description = "Names of the result sets to combine")]
+    sets: Vec<String>,
+    #[serde(default)]
+    #[schemars(description = "Name to save the results under, to combine them later. Defaults to `result`")]
+    save_as: Option<String>,
+    #[serde(flatten)]
+    page: PageArgs,
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(description = "Removes the tags of some result sets from another one")]
+struct SubtractArgs {
+    #[schemars(description = "The result set to remove tags from")]
+    from: String,
+    #[schemars(description = "The result sets whose tags are removed")]
+    remove: Vec<String>,
+    #[serde(default)]
+    #[schemars(description = "Name to save the results under, to combine them later. Defaults to `result`")]
+    save_as: Option<String>,
+    #[serde(flatten)]
+    page: PageArgs,
+}
+
+#[derive(Default, Clone, Copy, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct PageArgs {
+    #[serde(default)]
+    #[schemars(description = "How many results to skip, to see the next page of an earlier search")]
+    pub offset: usize,
+    #[serde(default)]
+    #[schemars(description = "How many results to show at most, defaults to 50")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(description = "Reads the code of a file in the project, to check a candidate before picking it")]
+struct ReadFileArgs {
+    #[schemars(description = "The file to read, relative to the project or absolute")]
+    path: PathBuf,
+    #[serde(default)]
+    #[schemars(description = "The first line to read, defaults to the start of the file")]
+    from: Option<u64>,
+    #[serde(default)]
+    #[schemars(description = "The last line to read, defaults to the end of the file")]
+    to: Option<u64>,
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(description = "Lists the directories of the project as a tree, with the number of files and the most common languages of each")]
+struct ListDirArgs {
+    #[serde(default)]
+    #[schemars(description = "The directory to list, relative to the project or absolute. Defaults to the project")]
+    path: Option<PathBuf>,
+    #[serde(default)]
+    #[schemars(description = "Levels of directories to show, defaults to 2")]
+    depth: Option<usize>,
+    #[serde(default)]
+    #[schemars(description = "Also list the files of each directory shown")]
+    files: bool,
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(description = "Run this function to stop searching, with the code where the predicate is found")]
+struct StopSearchingArgs {
+    #[schemars(description = "The code where the predicate is found, the best match first, if not found set to none")]
+    results: Option<Vec<ResultArgs>>,
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ResultArgs {
+    #[schemars(description = "The file of the code")]
+    path: PathBuf,
+    #[serde(default)]
+    #[schemars(description = "First line of the code, leave out for the whole file")]
+    from: Option<u32>,
+    #[serde(default)]
+    #[schemars(description = "Last line of the code, defaults to the first line")]
+    to: Option<u32>,
+    #[schemars(description = "How sure you are the code satisfies the predicate, from 0 to 1")]
+    confidence: f64,
+    #[schemars(description = "Why the code satisfies the predicate, in one line")]
+    justification: String,
+}
+
+impl ResultArgs {
+    /// The result at its path in the project, with the tags of the last search results in its lines.
+    pub fn found(self, project_dir: &Path, result: &[Ctag]) -> FoundFile {
+        let lines = self.from.map(|start| {
+            let end = self.to.unwrap_or(start).max(start);
+            LineRange { start, end }
+        });
+        let mut found = FoundFile::new(project_dir.join(self.path), lines, result);
+        found.confidence = Some(self.confidence.clamp(0.0, 1.0));
+        found.justification = Some(self.justification);
+        found
+    }
+}
+
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use itertools::Itertools;
+use tiktoken_rs::CoreBPE;
+use walkdir::WalkDir;
+use crate::ai::search::{resolve, ToolError};
+use crate::ctags::is_excluded_path;
+use crate::gitignore::GitIgnores;
+use crate::tiktoken::TokensLen;
+
+/// Languages shown per directory, the ones with the most files first.
+const LANGUAGES_SHOWN: usize = 3;
+
+/// Languages by file extension, files with any other extension only count towards the total.
+const LANGUAGES: &[(&str, &[&str])] = &[
+    ("Rust", &["rs"]),
+    ("Python", &["py", "pyi"]),
+    ("TypeScript", &["ts", "tsx", "mts", "cts"]),
+    ("JavaScript", &["js", "jsx", "mjs", "cjs"]),
+    ("Go", &["go"]),
+    ("C", &["c", "h"]),
+    ("C++", &["cpp", "cc", "cxx", "hpp", "hh"]),
+    ("C#", &["cs"]),
+    ("Java", &["java"]),
+    ("Kotlin", &["kt", "kts"]),
+    ("Swift", &["swift"]),
+    ("Ruby", &["rb"]),
+    ("PHP", &["php"]),
+    ("Shell", &["sh", "bash", "zsh"]),
+    ("HTML", &["html", "htm"]),
+    ("CSS", &["css", "scss", "sass"]),
+    ("Markdown", &["md"]),
+    ("TOML", &["toml"]),
+    ("YAML", &["yaml", "yml"]),
+    ("JSON", &["json"]),
+    ("SQL", &["sql"]),
+];
+
+fn language(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?;
+    LANGUAGES
+        .iter()
+        .find(|(_, extensions)| extensions.contains(&extension))
+        .map(|(name, _)| *name)
+}
+
+#[derive(Debug, Default)]
+struct Dir {
+    /// Files in the directory and every directory below it.
+    files: usize,
+    languages: BTreeMap<&'static str, usize>,
+    /// Names of the files right in the directory.
+    own_files: Vec<String>,
+}
+
+/// Renders the directories below `path` down to `depth` levels, each with the number of files
+/// under it and its most common languages, and with its files when `files` is set. Blacklisted
+/// and git ignored paths are left out. The tree is cut short at `max_tokens`.
+pub fn list_dir(
+    project_dir: &Path,
+    blacklist: &[&Path],
+    path: &Path,
+    depth: usize,
+    files: bool,
+    bpe: &CoreBPE,
+    max_tokens: usize,
+) -> Result<String, ToolError> {
+    let root = resolve(project_dir, blacklist, path)?;
+    let project_dir = project_dir.canonicalize().map_err(|e| ToolError::Unreadable {
+        path: project_dir.to_path_buf(),
+        reason: e.to_string(),
+    })?;
+
+    let mut ignores = GitIgnores::new(&project_dir);
+    let walker = WalkDir::new(&root).sort_by_file_name().into_iter().filter_entry(|entry| {
+        let relative = entry.path().strip_prefix(&project_dir).unwrap_or(entry.path());
+        entry.depth() == 0
+            || !(is_excluded_path(relative, blacklist) || ignores.is_ignored(entry.path(), entry.file_type().is_dir()))
+    });
+
+    let mut dirs = BTreeMap::<PathBuf, Dir>::new();
+    for entry in walker.filter_map(Result::ok) {
+        let relative = entry.path().strip_prefix(&root).unwrap_or(entry.path()).to_path_buf();
+        if entry.file_type().is_dir() {
+            dirs.entry(relative).or_default();
+            continue;
+        }
+
+        let language = language(entry.path());
+        for dir in relative.ancestors().skip(1) {
+            let dir = dirs.entry(dir.to_path_buf()).or_default();
+            dir.files += 1;
+            if let Some(language) = language {
+                *dir.languages.entry(language).or_default() += 1;
+            }
+        }
+        if let Some(dir) = dirs.get_mut(relative.parent().unwrap_or(Path::new(""))) {
+            dir.own_files.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+
+    let mut lines = vec![];
+    for (relative, dir) in &dirs {
+        let level = relative.components().count();
+        if level > depth {
+            continue;
+        }
+
+        let name = match relative.file_name() {
+            Some(name) => format!("{}/", name.to_string_lossy()),
+            None => match root.strip_prefix(&project_dir).unwrap_or(&root) {
+                top if top.as_os_str().is_empty() => "./".to_string(),
+                top => format!("{}/", top.display()),
+            },
+        };
+        let languages = dir
+            .languages
+            .iter()
+            .sorted_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)))
+            .take(LANGUAGES_SHOWN)
+            .map(|(language, count)| format!("{language} {count}"))
+            .join(", ");
+        let plural = if dir.files == 1 { "" } else { "s" };
+        let mut line = format!("{}{name} {} file{plural}", "  ".repeat(level), dir.files);
+        if !languages.is_empty() {
+            line.push_str(&format!(", {languages}"));
+        }
+        lines.push(line);
+
+        if files && level < depth {
+            lines.extend(dir.own_files.iter().map(|file| format!("{}{file}", "  ".repeat(level + 1))));
+        }
+    }
+
+    let mut out = String::new();
+    let mut tokens = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let line = format!("{line}\n");
+        tokens += line.token_len(bpe);
+        if i > 0 && tokens > max_tokens {
+            out.push_str(&format!("{} more lines, list a subdirectory to see them\n", lines.len() - i));
+            break;
+        }
+        out.push_str(&line);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use crate::ai::search::tree::list_dir;
+    use crate::tiktoken::bpe_for;
+
+    #[test]
+    fn counts_files_and_languages_per_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for file in ["src/main.rs", "src/ai/search.rs", "src/ai/finder.md", "Cargo.toml", "target/debug/out.rs", "web/app.ts"] {
+            fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+            fs::write(root.join(file), "").unwrap();
+        }
+        let bpe = bpe_for("gpt-4");
+
+        let tree = list_dir(root, &[Path::new("target")], Path::new(""), 1, false, &bpe, 1000).unwrap();
+        assert_eq!(
+            tree,
+            "./ 5 files, Rust 2, Markdown 1, TOML 1\n  src/ 3 files, Rust 2, Markdown 1\n  web/ 1 file, TypeScript 1\n"
+        );
+
+        let tree = list_dir(root, &[], Path::new("src"), 2, true, &bpe, 1000).unwrap();
+        assert_eq!(
+            tree,
+            "src/ 3 files, Rust 2, Markdown 1\n  main.rs\n  ai/ 2 files, Markdown 1, Rust 1\n    finder.md\n    search.rs\n"
+        );
+
+        let tree = list_dir(root, &[], Path::new(""), 3, false, &bpe, 1).unwrap();
+        assert!(tree.ends_with("5 more lines, list a subdirectory to see them\n"));
+    }
+}
@@ -0,0 +1,290 @@
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use itertools::Itertools;
+use log::debug;
+use openai_utils::FunctionCall;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::{from_str, Value};
+use thiserror::Error;
+use tiktoken_rs::CoreBPE;
+use crate::ai::wire::ToolSpec;
+
+/// A function an agent can call, run with the context `C` of the agent calling it. Tools are
+/// called on a blocking thread, they are free to read files.
+pub trait Tool<C: Sync>: Send + Sync {
+    /// The arguments, their schema is what the agent is told about the tool.
+    type Args: DeserializeOwned + JsonSchema + Debug + Send;
+    type Output;
+
+    /// The name the agent calls the tool by.
+    fn name(&self) -> &'static str;
+
+    fn execute(&self, args: Self::Args, context: &C) -> anyhow::Result<Self::Output>;
+}
+
+/// The project a tool looks at, for tools that any agent working on the project can use.
+pub trait Project: Sync {
+    fn project_dir(&self) -> &Path;
+    fn blacklist(&self) -> &[PathBuf];
+    /// The tokenizer of the agent, results are cut short to fit in [`Project::max_result_tokens`].
+    fn bpe(&self) -> &CoreBPE;
+    fn max_result_tokens(&self) -> usize;
+}
+
+/// A call the agent got wrong, told to it so it can try again.
+#[derive(Debug, Error)]
+pub enum CallError {
+    #[error("there is no tool named `{name}`, the tools are:\n{tools}")]
+    UnknownTool { name: String, tools: String },
+    #[error("invalid arguments for {tool}: {reason}\n{usage}")]
+    InvalidArguments { tool: String, reason: String, usage: String },
+}
+
+/// The tools of an agent by name, with their results turned into `O`. Tells the agent about them
+/// and runs its calls.
+pub struct Registry<C, O> {
+    tools: Vec<Box<dyn Call<C, O>>>,
+}
+
+impl<C, O> Default for Registry<C, O> {
+    fn default() -> Self {
+        Self { tools: vec![] }
+    }
+}
+
+impl<C: Sync, O> Registry<C, O> {
+    pub fn register<T>(&mut self, tool: T) -> &mut Self
+    where
+        T: Tool<C> + 'static,
+        T::Output: Into<O>,
+    {
+        let spec = ToolSpec::function::<T::Args>(tool.name());
+        assert!(self.get(tool.name()).is_none(), "tool {} is registered twice", tool.name());
+        self.tools.push(Box::new(Registered { tool, spec }));
+        self
+    }
+
+    /// What the agent is told about the tools, in the order they were registered.
+    pub fn specs(&self) -> Vec<ToolSpec> {
+        self.tools.iter().map(|tool| tool.spec().clone()).collect()
+    }
+
+    /// One line on every tool.
+    pub fn help(&self) -> String {
+        self.tools
+            .iter()
+            .map(|tool| {
+                let function = &tool.spec().function;
+                format!("- `{}`: {}", function.name, function.description.as_deref().unwrap_or("no description"))
+            })
+            .join("\n")
+    }
+
+    /// Runs `call` with `context`. A tool that doesn't exist or arguments that don't fit its schema
+    /// are a [`CallError`].
+    pub fn call(&self, call: &FunctionCall, context: &C) -> anyhow::Result<O> {
+        let tool = self.get(&call.name).ok_or_else(|| CallError::UnknownTool {
+            name: call.name.clone(),
+            tools: self.help(),
+        })?;
+        tool.call(&call.arguments, context)
+    }
+
+    fn get(&self, name: &str) -> Option<&dyn Call<C, O>> {
+        self.tools.iter().find(|tool| tool.spec().function.name == name).map(Box::as_ref)
+    }
+}
+
+/// A registered tool with the types of its arguments and result erased.
+trait Call<C, O>: Send + Sync {
+    fn spec(&self) -> &ToolSpec;
+    fn call(&self, arguments: &str, context: &C) -> anyhow::Result<O>;
+}
+
+struct Registered<T> {
+    tool: T,
+    spec: ToolSpec,
+}
+
+impl<C, O, T> Call<C, O> for Registered<T>
+where
+    C: Sync,
+    T: Tool<C>,
+    T::Output: Into<O>,
+{
+    fn spec(&self) -> &ToolSpec {
+        &self.spec
+    }
+
+    fn call(&self, arguments: &str, context: &C) -> anyhow::Result<O> {
+        let args = parse::<T::Args>(&self.spec, arguments)?;
+        debug!("Executing {} with args: {args:?}", self.spec.function.name);
+        Ok(self.tool.execute(args, context)?.into())
+    }
+}
+
+/// Parses the arguments of a call of the tool of `spec`. They are checked against its schema
+/// first, so the agent is told which argument is wrong and not only that something is.
+fn parse<A: DeserializeOwned>(spec: &ToolSpec, arguments: &str) -> Result<A, CallError> {
+    let invalid = |reason: String| CallError::InvalidArguments {
+        tool: spec.function.name.clone(),
+        reason,
+        usage: usage(spec),
+    };
+
+    // some models send nothing at all for a tool without arguments
+    let arguments = if arguments.trim().is_empty() { "{}" } else { arguments };
+    let value = from_str::<Value>(arguments).map_err(|e| invalid(format!("they are not JSON, {e}")))?;
+    check(&spec.function.parameters, &value).map_err(invalid)?;
+    serde_json::from_value(value).map_err(|e| invalid(e.to_string()))
+}
+
+/// Checks the names and types of `arguments` against the properties of `schema`.
+fn check(schema: &Value, arguments: &Value) -> Result<(), String> {
+    let Value::Object(arguments) = arguments else {
+        return Err(format!("they must be an object, not {arguments}"));
+    };
+    let properties = schema.get("properties").and_then(Value::as_object);
+
+    for (name, value) in arguments {
+        let Some(property) = properties.and_then(|p| p.get(name)) else {
+            return Err(format!("there is no argument `{name}`"));
+        };
+        let types = types(property);
+        if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+            return Err(format!("`{name}` must be {}, not {value}", types.iter().map(|t| with_article(t)).join(" or ")));
+        }
+        let minimum = property.get("minimum").and_then(Value::as_f64);
+        if let Some(minimum) = minimum.filter(|min| value.as_f64().is_some_and(|v| v < *min)) {
+            return Err(format!("`{name}` must be at least {minimum}, not {value}"));
+        }
+    }
+
+    let mut required = schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str);
+    match required.find(|name| !arguments.contains_key(*name)) {
+        Some(missing) => Err(format!("`{missing}` is missing")),
+        None => Ok(()),
+    }
+}
+
+/// The JSON types a property of a schema allows, none when the schema doesn't say.
+fn types(property: &Value) -> Vec<&str> {
+    match property.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    }
+}
+
+fn has_type(value: &Value, json_type: &str) -> bool {
+    match json_type {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn with_article(json_type: &str) -> String {
+    match json_type {
+        "null" => json_type.to_string(),
+        "integer" | "array" | "object" => format!("an {json_type}"),
+        _ => format!("a {json_type}"),
+    }
+}
+
+/// The arguments of the tool of `spec`, one per line.
+fn usage(spec: &ToolSpec) -> String {
+    let parameters = &spec.function.parameters;
+    let Some(properties) = parameters.get("properties").and_then(Value::as_object).filter(|p| !p.is_empty()) else {
+        return format!("{} takes no arguments", spec.function.name);
+    };
+    let required = parameters.get("required").and_then(Value::as_array);
+
+    let arguments = properties.iter().map(|(name, property)| {
+        let mut kind = types(property).into_iter().filter(|t| *t != "null").join(" or ");
+        if required.is_some_and(|r| r.iter().any(|r| r == name)) {
+            kind.push_str(", required");
+        }
+        let description = property.get("description").and_then(Value::as_str).unwrap_or_default();
+        format!("- `{name}` ({kind}): {description}")
+    });
+    format!("{} takes:\n{}", spec.function.name, arguments.format("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+        use openai_utils::FunctionCall;
+    use schemars::JsonSchema;
+    use serde_derive::Deserialize;
+    use crate::ai::tool::{Registry, Tool};
+
+    #[derive(Debug, Deserialize, JsonSchema)]
+    #[schemars(description = "Adds a number to the base")]
+    struct AddArgs {
+        #[schemars(description = "The number to add")]
+        n: i64,
+        #[serde(default)]
+        #[schemars(description = "How often to add it")]
+        times: Option<u32>,
+    }
+
+    struct Add;
+
+    impl Tool<i64> for Add {
+        type Args = AddArgs;
+        type Output = i64;
+
+        fn name(&self) -> &'static str {
+            "add"
+        }
+
+        fn execute(&self, args: AddArgs, base: &i64) -> anyhow::Result<i64> {
+            Ok(base + args.n * i64::from(args.times.unwrap_or(1)))
+        }
+    }
+
+    fn registry() -> Registry<i64, i64> {
+        let mut registry = Registry::default();
+        registry.register(Add);
+        registry
+    }
+
+    fn call(name: &str, arguments: &str) -> Result<i64, String> {
+        let call = FunctionCall { name: name.to_string(), arguments: arguments.to_string() };
+        registry().call(&call, &10).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn dispatches_calls_by_name() {
+        assert_eq!(call("add", r#"{"n": 2, "times": 3}"#), Ok(16));
+        assert_eq!(call("add", r#"{"n": -2, "times": null}"#), Ok(8));
+        assert_eq!(
+            call("sub", "{}"),
+            Err("there is no tool named `sub`, the tools are:\n- `add`: Adds a number to the base".to_string())
+        );
+
+        let specs = registry().specs();
+        assert_eq!(specs[0].kind, "function");
+        assert_eq!(specs[0].function.name, "add");
+    }
+
+    #[test]
+    fn tells_what_is_wrong_with_the_arguments() {
+        let usage = "add takes:\n- `n` (integer, required): The number to add\n- `times` (integer): How often to add it";
+        let error = |reason: &str| Err(format!("invalid arguments for add: {reason}\n{usage}"));
+
+        assert_eq!(call("add", ""), error("`n` is missing"));
+        assert_eq!(call("add", r#"{"n": "2"}"#), error(r#"`n` must be an integer, not "2""#));
+        assert_eq!(call("add", r#"{"n": 2, "times": -1}"#), error("`times` must be at least 0, not -1"));
+        assert_eq!(call("add", r#"{"n": 2, "times": "2"}"#), error(r#"`times` must be an integer or null, not "2""#));
+        assert_eq!(call("add", r#"{"n": 2, "count": 3}"#), error("there is no argument `count`"));
+        assert_eq!(call("add", "[2]"), error("they must be an object, not [2]"));
+        assert!(call("add", "{n: 2}").unwrap_err().starts_with("invalid arguments for add: they are not JSON, "));
+    }
+}
@@ -0,0 +1,100 @@
+use openai_utils::{Function, FunctionCall, Usage};
+use schemars::{schema_for, JsonSchema};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A message of a chat as it goes over the wire, the message of `openai-utils` plus the tool
+/// calls of the assistant and the call a tool message answers.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Message {
+    pub role: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl Message {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: "user".to_string(), content: Some(content.into()), ..Default::default() }
+    }
+
+    /// The result of the tool call `id`.
+    pub fn tool(id: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: "tool".to_string(),
+            content: Some(content.into()),
+            tool_call_id: Some(id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// The tool calls of an assistant message, none for a plain reply.
+    pub fn tool_calls(&self) -> &[ToolCall] {
+        self.tool_calls.as_deref().unwrap_or_default()
+    }
+}
+
+impl From<openai_utils::Message> for Message {
+    fn from(message: openai_utils::Message) -> Self {
+        Self { role: message.role, content: message.content, name: message.name, ..Default::default() }
+    }
+}
+
+/// A call of one of the tools of a request, the assistant can make several in one message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: FunctionCall,
+}
+
+/// A tool the assistant may call, a function taking arguments of a JSON schema.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolSpec {
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: Function,
+}
+
+impl ToolSpec {
+    /// The function `name` taking `A`, described by the description of its schema.
+    pub fn function<A: JsonSchema>(name: &str) -> Self {
+        let parameters = serde_json::to_value(schema_for!(A)).expect("schemas serialize");
+        let description = parameters.get("description").and_then(Value::as_str).map(str::to_string);
+        Self {
+            kind: function_type(),
+            function: Function { name: name.to_string(), description, parameters },
+        }
+    }
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+/// The response to a completion request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Completion {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub model: String,
+    pub choices: Vec<Choice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Choice {
+    #[serde(default)]
+    pub index: i64,
+    pub message: Message,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
@@ -0,0 +1,211 @@
+use std::io::{stdin, stdout, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use anyhow::anyhow;
+use clap::{Args, Parser, Subcommand};
+use log::{warn, LevelFilter};
+use openai_macros::ai_agent;
+use toml::{Table, Value};
+use crate::ai::{blacklist, known_blacklist, provider, AgentSettings};
+use crate::ai::budget::StopReason;
+use crate::ai::provider::{CompletionRequest, Provider};
+use crate::ai::search::find_file;
+use crate::ai::wire::Message;
+use crate::config::{self, CONFIG};
+use crate::ctags::backend::TagBackendKind;
+use crate::ctags::CtagsOutput;
+use crate::output::{write_results, OutputFormat};
+use crate::as_paths;
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Find code in a project by asking for it",
+    after_help = "Exit status is 0 when something was found, 1 when a search found nothing, 2 on errors and 3 when a \
+        search hit one of its limits and only has the best candidates so far."
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    #[command(flatten)]
+    pub global: GlobalArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct GlobalArgs {
+    /// Project to work on, overrides the `project_dir` of every config layer
+    #[arg(short = 'C', long, global = true)]
+    pub project_dir: Option<PathBuf>,
+
+    /// Model of every agent that doesn't configure its own
+    #[arg(short, long, global = true)]
+    pub model: Option<String>,
+
+    /// Write every request and response of the session to a cassette file
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Answer requests from a recorded cassette instead of the api
+    #[arg(long, global = true, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+
+    /// Log more, repeat for even more output
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only log errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Search the project for the code matching a description
+    Search {
+        query: String,
+
+        /// How to print the results
+        #[arg(short, long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Bring the tag index of the project up to date
+    Index(TagArgs),
+    /// Print the root entries left out of the project
+    Blacklist,
+    /// Print the tags of the project as JSON lines
+    Tags(TagArgs),
+    /// Print the effective configuration and where each value comes from
+    Config,
+    /// Chat with the model, reads one message per line from stdin
+    Chat,
+}
+
+#[derive(Args, Debug)]
+pub struct TagArgs {
+    /// Tag backend to use, defaults to ctags when it is installed
+    #[arg(long, value_enum)]
+    pub backend: Option<TagBackendKind>,
+
+    /// Paths to leave out, relative to the project directory
+    #[arg(long)]
+    pub exclude: Vec<PathBuf>,
+}
+
+impl GlobalArgs {
+    pub fn log_level(&self) -> LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => LevelFilter::Error,
+            (false, 0) => LevelFilter::Info,
+            (false, 1) => LevelFilter::Debug,
+            (false, _) => LevelFilter::Trace,
+        }
+    }
+
+    /// The flags as the topmost config layer.
+    pub fn config_layer(&self) -> anyhow::Result<Table> {
+        let mut layer = Table::new();
+        if let Some(project_dir) = &self.project_dir {
+            let project_dir = std::path::absolute(project_dir)?;
+            layer.insert("project_dir".to_string(), Value::String(project_dir.display().to_string()));
+        }
+        if let Some(model) = &self.model {
+            layer.insert("model".to_string(), Value::String(model.clone()));
+        }
+
+        let mut provider = Table::new();
+        if let Some(record) = &self.record {
+            provider.insert("record".to_string(), Value::String(std::path::absolute(record)?.display().to_string()));
+        }
+        if let Some(replay) = &self.replay {
+            provider.insert("replay".to_string(), Value::String(std::path::absolute(replay)?.display().to_string()));
+        }
+        if !provider.is_empty() {
+            layer.insert("provider".to_string(), Value::Table(provider));
+        }
+        Ok(layer)
+    }
+}
+
+impl Cli {
+    pub async fn run(self) -> anyhow::Result<ExitCode> {
+        config::load(self.global.config_layer()?)?;
+
+        match self.command {
+            Command::Search { query, format } => {
+                let provider = provider::configured()?;
+                let blacklist = blacklist(provider.as_ref()).await?;
+                let outcome = find_file(provider.as_ref(), &query, blacklist).await?;
+                if outcome.reason != StopReason::Answered {
+                    warn!("search ended early, {}, showing the best candidates so far", outcome.reason);
+                }
+                return write_results(&mut stdout().lock(), format, outcome.found.as_deref(), outcome.reason);
+            }
+            Command::Index(args) => {
+                let tags = args.get_tags().await?;
+                println!("{} tags indexed", tags.0.len());
+            }
+            Command::Blacklist => {
+                for entry in blacklist(provider::configured()?.as_ref()).await? {
+                    println!("{}", entry.display());
+                }
+            }
+            Command::Tags(args) => {
+                let mut out = stdout().lock();
+                for tag in args.get_tags().await?.0 {
+                    writeln!(out, "{}", serde_json::to_string(&tag)?)?;
+                }
+            }
+            Command::Config => {
+                print!("{}", config::describe());
+            }
+            Command::Chat => chat(provider::configured()?.as_ref()).await?,
+        }
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+impl TagArgs {
+    /// The tags of the project without the blacklist, so the index holds the same files `search`
+    /// keeps in it. The blacklist agent isn't asked, only its cached answers are used.
+    async fn get_tags(&self) -> anyhow::Result<CtagsOutput> {
+        if let Some(backend) = self.backend {
+            CONFIG.write().unwrap().tag_backend = Some(backend);
+        }
+        let mut excluded = known_blacklist().await?;
+        excluded.extend(self.exclude.iter().cloned());
+        CtagsOutput::get_tags(&as_paths(&excluded))
+    }
+}
+
+const CHAT_PROMPT: &str = "You are a helpful assistant for software developers.";
+
+async fn chat(provider: &dyn Provider) -> anyhow::Result<()> {
+    let settings = AgentSettings::load("chat", CHAT_PROMPT);
+    let mut agent = ai_agent! {
+        model: settings.model.clone(),
+        temperature: settings.temperature,
+        system_message: settings.system_prompt.as_str(),
+    };
+    agent.max_tokens = settings.max_tokens;
+    let mut request = CompletionRequest::new(&agent, &settings);
+
+    let mut line = String::new();
+    while stdin().read_line(&mut line)? != 0 {
+        let content = line.trim();
+        if !content.is_empty() {
+            request.messages.push(Message::user(content));
+
+            let res = provider.complete(&request).await?;
+            let message = res.choices.into_iter().next().ok_or_else(|| anyhow!("the chat agent got no reply"))?.message;
+            println!("{}", message.content.as_deref().unwrap_or_default());
+            stdout().flush()?;
+
+            request.messages.push(message);
+        }
+        line.clear();
+    }
+
+    Ok(())
+}
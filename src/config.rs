@@ -1,80 +1,411 @@
+use crate::ai::blacklist::BlacklistConfig;
+use crate::ai::budget::{SearchConfig, DEFAULT_MAX_RESULT_TOKENS, DEFAULT_MAX_ROUNDS};
+use crate::ai::provider::{ProviderConfig, DEFAULT_API_KEY_ENV, DEFAULT_BASE_URL};
+use crate::ai::DEFAULT_MODEL;
+use crate::ctags::backend::TagBackendKind;
+use anyhow::Context;
+use log::debug;
 use once_cell::sync::Lazy;
-use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
+use std::{env, fs};
+use toml::{Table, Value};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
     pub project_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// `ctags` or `tree-sitter`, picks ctags when it is installed if left out.
+    #[serde(default)]
+    pub tag_backend: Option<TagBackendKind>,
+    /// Per agent settings, keyed by agent name (`blacklist`, `finder`, `chat`).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub agents: BTreeMap<String, AgentConfig>,
+    #[serde(default)]
+    pub provider: ProviderConfig,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub blacklist: BlacklistConfig,
 }
 
-const CONFIG_FILE: &str = "config.toml";
+/// The `[agents.<name>]` section, anything left out falls back to the defaults of the agent.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct AgentConfig {
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Replaces the built-in system prompt of the agent.
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+}
+
+/// File name of the per-project config, searched for from the current directory upwards.
+pub const PROJECT_CONFIG_FILE: &str = ".devgpt.toml";
+const GLOBAL_CONFIG_FILE: &str = "config.toml";
+const ENV_PREFIX: &str = "DEVGPT_";
 
 pub type AppConfig = Arc<RwLock<Config>>;
 
+/// Starts out empty, [`load`] fills it in from every layer.
 pub static CONFIG: Lazy<AppConfig> = Lazy::new(|| {
-    println!("initializing config.");
-    AppConfig::open()
+    debug!("initializing config.");
+    AppConfig::default()
 });
 
-impl Config {
-    pub fn open() -> Self {
-        let mut file = File::open(CONFIG_FILE).unwrap_or_else(|_| {
-            let mut f = File::create(CONFIG_FILE).unwrap();
-            f.write_all(
-                toml::to_string_pretty(&Config::default())
-                    .unwrap()
-                    .as_bytes(),
-            )
-            .unwrap();
-            File::open(CONFIG_FILE).unwrap()
-        });
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).unwrap();
-        toml::from_str(&contents).unwrap_or_default()
+static SOURCES: Lazy<RwLock<Sources>> = Lazy::new(Default::default);
+
+/// Where the effective value of a config key came from, later layers win.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    Global(PathBuf),
+    Project(PathBuf),
+    Env(String),
+    Cli,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Global(path) => write!(f, "global config {}", path.display()),
+            Source::Project(path) => write!(f, "project config {}", path.display()),
+            Source::Env(var) => write!(f, "environment variable {var}"),
+            Source::Cli => write!(f, "command line"),
+        }
     }
+}
+
+/// Sources of every key that is set, keyed by dotted path like `agents.finder.model`.
+pub type Sources = BTreeMap<String, Source>;
+
+/// The merged config together with the source of every key in it.
+#[derive(Debug, Clone)]
+pub struct Layered {
+    pub config: Config,
+    pub sources: Sources,
+}
+
+/// Loads every layer, with `cli` on top, into [`CONFIG`].
+pub fn load(cli: Table) -> anyhow::Result<()> {
+    let layered = Layered::load(global_config_path(), &env::current_dir()?, env::vars(), cli)?;
+    *CONFIG.write().unwrap() = layered.config;
+    *SOURCES.write().unwrap() = layered.sources;
+    Ok(())
+}
+
+/// Renders the effective config, one `key = value` line per key with the layer it came from.
+pub fn describe() -> String {
+    let layered = Layered { config: CONFIG.read().unwrap().clone(), sources: SOURCES.read().unwrap().clone() };
+    layered.describe()
+}
 
-    pub fn save(&self) {
-        let mut file = File::create(CONFIG_FILE).unwrap();
-        let contents = toml::to_string_pretty(self).unwrap();
-        file.write_all(contents.as_bytes()).unwrap();
+/// The config with the built-in default of every key that has one, what leaving a key out means.
+fn defaults() -> Config {
+    Config {
+        model: Some(DEFAULT_MODEL.to_string()),
+        provider: ProviderConfig {
+            base_url: Some(DEFAULT_BASE_URL.to_string()),
+            api_key_env: Some(DEFAULT_API_KEY_ENV.to_string()),
+            ..Default::default()
+        },
+        search: SearchConfig {
+            max_rounds: Some(DEFAULT_MAX_ROUNDS),
+            max_result_tokens: Some(DEFAULT_MAX_RESULT_TOKENS),
+            ..Default::default()
+        },
+        blacklist: BlacklistConfig { ask_model: Some(true), ..Default::default() },
+        ..Default::default()
     }
 }
 
-pub trait ConfigTrait {
-    fn save(&self);
-    fn open() -> Self;
-    // fn repo_location(&self) -> Option<PathBuf>;
-    // fn project_summary(&self) -> String;
-    // fn set_repo_location(&self, repo_location: PathBuf);
-    // fn set_project_summary(&self, project_summary: String);
+impl Layered {
+    /// Every key that is set or has a default, keys no layer sets are marked `# default`.
+    pub fn describe(&self) -> String {
+        let table = Value::try_from(&self.config).ok().and_then(|v| v.as_table().cloned()).unwrap_or_default();
+        let defaults = Value::try_from(defaults()).ok().and_then(|v| v.as_table().cloned()).unwrap_or_default();
+
+        let mut sources = Sources::new();
+        for (key, value) in &defaults {
+            record(value, &Source::Default, key, &mut sources);
+        }
+        sources.extend(self.sources.clone());
+
+        let mut out = String::new();
+        for (key, source) in &sources {
+            if let Some(value) = lookup(&table, key).or_else(|| lookup(&defaults, key)) {
+                out.push_str(&format!("{key} = {value}  # {source}\n"));
+            }
+        }
+        out
+    }
+
+    pub fn load(
+        global: Option<PathBuf>,
+        cwd: &Path,
+        env: impl IntoIterator<Item = (String, String)>,
+        cli: Table,
+    ) -> anyhow::Result<Self> {
+        let mut table = Table::new();
+        let mut sources = Sources::new();
+
+        if let Some(path) = global.filter(|p| p.is_file()) {
+            let mut layer = read_table(&path)?;
+            resolve_project_dir(&mut layer, path.parent().expect("config file has a parent"));
+            merge(&mut table, layer, &Source::Global(path), "", &mut sources);
+        }
+
+        if let Some(path) = find_project_config(cwd) {
+            let dir = path.parent().expect("config file has a parent");
+            let mut layer = read_table(&path)?;
+            // a missing project dir is the file's directory
+            if !layer.contains_key("project_dir") {
+                layer.insert("project_dir".to_string(), Value::String(dir.display().to_string()));
+            }
+            resolve_project_dir(&mut layer, dir);
+            merge(&mut table, layer, &Source::Project(path), "", &mut sources);
+        }
+
+        for (var, value) in env {
+            let Some(key) = var.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let mut layer = env_table(key, &value);
+            resolve_project_dir(&mut layer, cwd);
+            merge(&mut table, layer, &Source::Env(var), "", &mut sources);
+        }
+
+        merge(&mut table, cli, &Source::Cli, "", &mut sources);
+
+        if !table.contains_key("project_dir") {
+            table.insert("project_dir".to_string(), Value::String(cwd.display().to_string()));
+            sources.insert("project_dir".to_string(), Source::Default);
+        }
+
+        let config = Value::Table(table).try_into::<Config>().context("invalid configuration")?;
+
+        Ok(Self { config, sources })
+    }
 }
 
-impl ConfigTrait for AppConfig {
-    fn save(&self) {
-        let config = self.read().unwrap();
-        config.save();
+/// `$XDG_CONFIG_HOME/devgpt/config.toml`, falling back to `~/.config`, or `%APPDATA%` on windows.
+pub fn global_config_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+
+    Some(dir.join("devgpt").join(GLOBAL_CONFIG_FILE))
+}
+
+fn find_project_config(cwd: &Path) -> Option<PathBuf> {
+    cwd.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+fn read_table(path: &Path) -> anyhow::Result<Table> {
+    let contents = fs::read_to_string(path).with_context(|| format!("could not read {}", path.display()))?;
+    toml::from_str(&contents).with_context(|| format!("could not parse {}", path.display()))
+}
+
+/// Makes a relative `project_dir` of a layer relative to `dir`, the directory of its file or the
+/// current directory.
+fn resolve_project_dir(layer: &mut Table, dir: &Path) {
+    if let Some(Value::String(project_dir)) = layer.get_mut("project_dir") {
+        *project_dir = dir.join(&*project_dir).display().to_string();
     }
+}
+
+/// Keys of the config that aren't strings, `*` stands for any agent. Env values of these are
+/// parsed as TOML, the values of every other key are taken as they are.
+const TYPED_KEYS: &[&str] = &[
+    "agents.*.temperature",
+    "agents.*.max_tokens",
+    "agents.*.seed",
+    "provider.json_schema",
+    "search.max_rounds",
+    "search.max_total_tokens",
+    "search.max_cost",
+    "search.max_result_tokens",
+    "search.overview",
+    "search.prompt_price",
+    "search.completion_price",
+    "blacklist.exclude",
+    "blacklist.keep",
+    "blacklist.ask_model",
+];
 
-    fn open() -> Self {
-        let config = Config::open();
-        Arc::new(RwLock::new(config))
+/// Turns `AGENTS__FINDER__MODEL=gpt-4` into `agents.finder.model = "gpt-4"`. Values of keys that
+/// are numbers, booleans or lists are parsed as TOML, so `SEARCH__MAX_ROUNDS=10` is a number while
+/// `MODEL=4` stays a string.
+fn env_table(key: &str, value: &str) -> Table {
+    let mut path = key.split("__").map(str::to_lowercase).collect::<Vec<_>>();
+    let typed = TYPED_KEYS.iter().any(|typed| {
+        let typed = typed.split('.').collect::<Vec<_>>();
+        typed.len() == path.len() && typed.iter().zip(&path).all(|(t, p)| *t == "*" || t == p)
+    });
+    let value = toml::from_str::<Table>(&format!("v = {value}"))
+        .ok()
+        .and_then(|mut t| t.remove("v"))
+        .filter(|_| typed)
+        .unwrap_or_else(|| Value::String(value.to_string()));
+
+    let last = path.pop().unwrap_or_default();
+
+    let mut table = Table::new();
+    table.insert(last, value);
+    for segment in path.into_iter().rev() {
+        let mut parent = Table::new();
+        parent.insert(segment, Value::Table(table));
+        table = parent;
+    }
+    table
+}
+
+/// Deep merges `layer` into `base`, tables are merged key by key, anything else is replaced.
+fn merge(base: &mut Table, layer: Table, source: &Source, prefix: &str, sources: &mut Sources) {
+    for (key, value) in layer {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") };
+
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge(base, layer, source, &path, sources),
+            (_, value) => {
+                sources.retain(|k, _| !k.starts_with(&format!("{path}.")));
+                record(&value, source, &path, sources);
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn record(value: &Value, source: &Source, path: &str, sources: &mut Sources) {
+    match value {
+        Value::Table(table) => {
+            for (key, value) in table {
+                record(value, source, &format!("{path}.{key}"), sources);
+            }
+        }
+        _ => {
+            sources.insert(path.to_string(), source.clone());
+        }
+    }
+}
+
+fn lookup<'a>(table: &'a Table, path: &str) -> Option<&'a Value> {
+    let mut segments = path.split('.');
+    let mut value = table.get(segments.next()?)?;
+    for segment in segments {
+        value = value.as_table()?.get(segment)?;
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Layered, Source, PROJECT_CONFIG_FILE};
+    use crate::ctags::backend::TagBackendKind;
+    use std::fs;
+    use toml::{Table, Value};
+
+    #[test]
+    fn later_layers_win() {
+        let dir = tempfile::tempdir().unwrap();
+        let global = dir.path().join("global.toml");
+        fs::write(&global, "model = \"global-model\"\ntag_backend = \"ctags\"\n").unwrap();
+        let project = dir.path().join("project");
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::write(project.join(PROJECT_CONFIG_FILE), "model = \"project-model\"\n").unwrap();
+
+        let env = [
+            ("DEVGPT_TAG_BACKEND".to_string(), "tree-sitter".to_string()),
+            ("OTHER".to_string(), "ignored".to_string()),
+        ];
+        let mut cli = Table::new();
+        cli.insert("model".to_string(), Value::String("cli-model".to_string()));
+
+        let layered = Layered::load(Some(global.clone()), &project.join("src"), env, cli).unwrap();
+
+        assert_eq!(layered.config.model.as_deref(), Some("cli-model"));
+        assert_eq!(layered.config.tag_backend, Some(TagBackendKind::TreeSitter));
+        assert_eq!(layered.config.project_dir, Some(project.clone()));
+        assert_eq!(layered.sources["model"], Source::Cli);
+        assert_eq!(layered.sources["tag_backend"], Source::Env("DEVGPT_TAG_BACKEND".to_string()));
+        assert_eq!(layered.sources["project_dir"], Source::Project(project.join(PROJECT_CONFIG_FILE)));
     }
 
-    // fn repo_location(&self) -> Option<PathBuf> {
-    //     let config = self.read().unwrap();
-    //     config.project_dir.clone()
-    // }
-    //
-    // fn set_repo_location(&self, repo_location: PathBuf) {
-    //     let mut config = self.write().unwrap();
-    //     config.project_dir = Some(repo_location);
-    // }
-    //
-    // fn set_project_summary(&self, project_summary: String) {
-    //     let mut config = self.write().unwrap();
-    //     config.project_summary = project_summary;
-    // }
+    #[test]
+    fn describes_set_keys_and_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let env = [("DEVGPT_SEARCH__MAX_ROUNDS".to_string(), "10".to_string())];
+
+        let described = Layered::load(None, dir.path(), env, Table::new()).unwrap().describe();
+        let lines = described.lines().collect::<Vec<_>>();
+
+        assert!(lines.contains(&"search.max_rounds = 10  # environment variable DEVGPT_SEARCH__MAX_ROUNDS"));
+        assert!(lines.contains(&"search.max_result_tokens = 2000  # default"));
+        assert!(lines.contains(&"model = \"gpt-4-1106-preview\"  # default"));
+        assert!(lines.contains(&format!("project_dir = {:?}  # default", dir.path()).as_str()));
+    }
+
+    #[test]
+    fn only_parses_env_values_of_typed_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let env = [
+            ("DEVGPT_MODEL", "4"),
+            ("DEVGPT_AGENTS__FINDER__MODEL", "true"),
+            ("DEVGPT_AGENTS__FINDER__TEMPERATURE", "0.5"),
+            ("DEVGPT_SEARCH__MAX_ROUNDS", "10"),
+            ("DEVGPT_BLACKLIST__EXCLUDE", "[\"fixtures\"]"),
+            ("DEVGPT_PROVIDER__BASE_URL", "[x]"),
+        ]
+        .map(|(var, value)| (var.to_string(), value.to_string()));
+
+        let config = Layered::load(None, dir.path(), env, Table::new()).unwrap().config;
+
+        assert_eq!(config.model.as_deref(), Some("4"));
+        assert_eq!(config.agents["finder"].model.as_deref(), Some("true"));
+        assert_eq!(config.agents["finder"].temperature, Some(0.5));
+        assert_eq!(config.search.max_rounds, Some(10));
+        assert_eq!(config.blacklist.exclude, ["fixtures"]);
+        assert_eq!(config.provider.base_url.as_deref(), Some("[x]"));
+    }
+
+    #[test]
+    fn resolves_relative_project_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let global = dir.path().join("config/global.toml");
+        fs::create_dir_all(global.parent().unwrap()).unwrap();
+        fs::write(&global, "project_dir = \"work\"\n").unwrap();
+        let cwd = dir.path().join("cwd");
+        fs::create_dir_all(&cwd).unwrap();
+
+        let layered = Layered::load(Some(global.clone()), &cwd, [], Table::new()).unwrap();
+        assert_eq!(layered.config.project_dir, Some(dir.path().join("config/work")));
+
+        let env = [("DEVGPT_PROJECT_DIR".to_string(), "../elsewhere".to_string())];
+        let layered = Layered::load(Some(global), &cwd, env, Table::new()).unwrap();
+        assert_eq!(layered.config.project_dir, Some(cwd.join("../elsewhere")));
+    }
+
+    #[test]
+    fn defaults_to_the_current_directory() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let layered = Layered::load(None, dir.path(), [], Table::new()).unwrap();
+
+        assert_eq!(layered.config.project_dir.as_deref(), Some(dir.path()));
+        assert_eq!(layered.sources["project_dir"], Source::Default);
+    }
 }
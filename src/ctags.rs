@@ -1,18 +1,33 @@
 use crate::config::CONFIG;
 use crate::tiktoken::TokensLen;
-use indicatif::{ProgressBar, ProgressStyle};
 use log::{debug, trace};
-use serde::de::Error;
+use anyhow::anyhow;
+use backend::backend;
+pub use error::CtagsError;
+pub use index::is_excluded_path;
+pub use kind::TagKind;
+pub use name::{best_first, NameMatcher};
+pub use path::{normalize, PathPattern};
+use index::TagIndex;
 use serde::{Deserialize, Serialize};
-use serde_json::from_str;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use tiktoken_rs::CoreBPE;
 
+pub mod backend;
+mod error;
+mod index;
+mod kind;
+mod name;
+mod path;
+mod treesitter;
+
+/// Same limit universal ctags applies to its search patterns.
+const PATTERN_LENGTH_LIMIT: usize = 96;
+
 #[derive(Serialize, Debug, Clone)]
 pub struct CtagsOutput(pub Vec<Ctag>);
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub struct Ctag {
     pub _type: String,
     #[serde(default)]
@@ -23,30 +38,51 @@ pub struct Ctag {
     pub pattern: Option<String>,
     #[serde(default)]
     pub parser_name: Option<String>,
+    /// The raw kind of the parser, see [`Ctag::canonical_kind`] for the same kind in every language.
     #[serde(default)]
     pub kind: Option<String>,
+    /// Set when the tag goes into the index.
     #[serde(default)]
-    pub scope: Option<String>,
+    pub canonical_kind: Option<TagKind>,
     #[serde(default)]
+    pub scope: Option<String>,
+    #[serde(default, alias = "scopeKind")]
     pub scope_kind: Option<String>,
     #[serde(default)]
     pub line: Option<u32>,
+    /// Last line of the definition.
+    #[serde(default)]
+    pub end: Option<u32>,
+    /// Parameters of a function, like `(path: &Path, depth: usize)`.
+    #[serde(default)]
+    pub signature: Option<String>,
+    /// Visibility, like `public` or `private`.
+    #[serde(default)]
+    pub access: Option<String>,
+    /// Type of a variable or return type of a function, like `typename:Config`.
+    #[serde(default)]
+    pub typeref: Option<String>,
+    /// Extra tag entries ctags marks, like `fileScope` or `reference`.
+    #[serde(default)]
+    pub extras: Option<String>,
 }
 
 impl Ctag {
+    /// Tags with a `_type` other than `tag` or `ptag`, from newer ctags versions, are neither.
     pub fn is_ptag(&self) -> bool {
-        match self._type.as_str() {
-            "tag" => false,
-            "ptag" => true,
-            _ => unimplemented!()
-        }
+        self._type == "ptag"
     }
 
     pub fn is_tag(&self) -> bool {
-        match self._type.as_str() {
-            "tag" => true,
-            "ptag" => false,
-            _ => unimplemented!()
+        self._type == "tag"
+    }
+
+    /// Whether the tag is of the kind the finder asks for, a canonical kind like `functions` for
+    /// every language, or else part of a raw kind like `implementation`.
+    pub fn kind_is(&self, kind: &str) -> bool {
+        match TagKind::parse(kind) {
+            Some(kind) => self.canonical_kind == Some(kind),
+            None => self.kind_contains(kind),
         }
     }
 
@@ -58,137 +94,68 @@ impl Ctag {
         }
     }
 
-    pub fn path_is(&self, path: &Path) -> bool {
-        if let Some(f) = &self.path {
-            f == path
-        } else {
-            false
+    /// Whether the definition overlaps the lines `from` to `to`, a tag without an end only covers
+    /// its first line.
+    pub fn spans(&self, from: u32, to: u32) -> bool {
+        match self.line {
+            Some(line) => line <= to && self.end.unwrap_or(line).max(line) >= from,
+            None => false,
         }
     }
 
-    pub fn name_contains(&self, name: &str) -> bool {
-        if let Some(f) = &self.name {
-            f.to_lowercase().contains(&name.to_lowercase())
-        } else {
-            false
-        }
+    pub fn path_matches(&self, pattern: &PathPattern) -> bool {
+        self.path.as_ref().is_some_and(|path| pattern.matches(path))
+    }
+
+    pub fn name_score(&self, matcher: &NameMatcher) -> Option<f64> {
+        self.name.as_deref().and_then(|name| matcher.score(name))
+    }
+}
+
+/// Builds a search pattern the way ctags does, e.g. `/^fn main() {$/`.
+pub fn pattern(line: &str) -> String {
+    let escaped = line.replace('\\', "\\\\").replace('/', "\\/");
+    if line.chars().count() > PATTERN_LENGTH_LIMIT {
+        let truncated = escaped.chars().take(PATTERN_LENGTH_LIMIT).collect::<String>();
+        format!("/^{truncated}/")
+    } else {
+        format!("/^{escaped}$/")
     }
 }
 
 impl CtagsOutput {
-    pub fn get_tags(blacklist: &[&Path]) -> Self {
-        let repo_location = CONFIG.read().unwrap().project_dir.clone().unwrap();
-
-        let blacklist: Vec<String> = blacklist
-            .iter()
-            .map(|p| format!("--exclude={}", p.display()))
-            .collect();
-
-        #[cfg(target_family = "windows")]
-        let mut proc = Command::new("ctags\\ctags.exe");
-        #[cfg(target_family = "unix")]
-        let mut proc = Command::new("ctags");
-
-        let res = proc
-            .args({
-                let mut vec = vec![
-                    "--languages=Rust,C,C++,C#,Java,JavaScript,Python,Ruby,Go,Kotlin,TypeScript,Elixir,Erlang,Haskell,Lua,Perl,PHP,PowerShell,SQL,Sh,Tcl,Asm,D,Fortran,Cobol,HTML,CSS,JavaProperties",
-                    "--kinddef-C=d,devgpt,devgpt-comments",
-                    "--regex-C=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-C++=d,devgpt,devgpt-comments",
-                    "--regex-C++=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-C#=d,devgpt,devgpt-comments",
-                    "--regex-C#=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Java=d,devgpt,devgpt-comments",
-                    "--regex-Java=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-JavaScript=d,devgpt,devgpt-comments",
-                    "--regex-JavaScript=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Python=d,devgpt,devgpt-comments",
-                    "--regex-Python=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Ruby=d,devgpt,devgpt-comments",
-                    "--regex-Ruby=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Go=d,devgpt,devgpt-comments",
-                    "--regex-Go=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Rust=d,devgpt,devgpt-comments",
-                    "--regex-Rust=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Kotlin=d,devgpt,devgpt-comments",
-                    "--regex-Kotlin=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-TypeScript=d,devgpt,devgpt-comments",
-                    "--regex-TypeScript=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Elixir=d,devgpt,devgpt-comments",
-                    "--regex-Elixir=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Erlang=d,devgpt,devgpt-comments",
-                    "--regex-Erlang=/%\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Haskell=d,devgpt,devgpt-comments",
-                    "--regex-Haskell=/--\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Lua=d,devgpt,devgpt-comments",
-                    "--regex-Lua=/--\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Perl=d,devgpt,devgpt-comments",
-                    "--regex-Perl=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-PHP=d,devgpt,devgpt-comments",
-                    "--regex-PHP=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-PowerShell=d,devgpt,devgpt-comments",
-                    "--regex-PowerShell=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-SQL=d,devgpt,devgpt-comments",
-                    "--regex-SQL=/--\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Sh=d,devgpt,devgpt-comments",
-                    "--regex-Sh=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Tcl=d,devgpt,devgpt-comments",
-                    "--regex-Tcl=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Asm=d,devgpt,devgpt-comments",
-                    "--regex-Asm=/;\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-D=d,devgpt,devgpt-comments",
-                    "--regex-D=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Fortran=d,devgpt,devgpt-comments",
-                    "--regex-Fortran=/!\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-Cobol=d,devgpt,devgpt-comments",
-                    "--regex-Cobol=/\\*\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--kinddef-HTML=d,devgpt,devgpt-comments",
-                    "--regex-HTML=/<!--\\s*DEV:\\s*(.*?)\\s*-->/\\1/d/",
-                    "--kinddef-CSS=d,devgpt,devgpt-comments",
-                    "--regex-CSS=/\\*\\s*DEV:\\s*(.*?)\\s*\\*\\//\\1/d/",
-                    "--kinddef-JavaProperties=d,devgpt,devgpt-comments",
-                    "--regex-JavaProperties=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
-                    "--fields=+n",
-                    "-R", "--output-format=json", "-f", "-"
-                ];
-
-                vec.extend(blacklist.iter().map(String::as_str));
-                vec.push(repo_location.to_str().unwrap());
-                vec
-            })
-            .output()
-            .unwrap();
-
-        trace!("ctags done executing");
-
-        let s = String::from_utf8(res.stdout).unwrap();
-
-        let res = Self(
-            s.lines()
-                .map(|s| from_str::<Ctag>(s.trim()).unwrap())
-                .collect(),
-        );
-
-        debug!("generated {} tags", res.0.len());
+    /// Returns the tags of the configured project, served from the on-disk index. Only files
+    /// that changed since the last call are handed to the tag backend again.
+    pub fn get_tags(blacklist: &[&Path]) -> anyhow::Result<Self> {
+        let config = CONFIG.read().unwrap().clone();
+        let project_dir = config.project_dir.ok_or_else(|| anyhow!("no project directory configured"))?;
+        let backend = backend(config.tag_backend);
+
+        let mut index = TagIndex::open(&project_dir, backend.name())?;
+        if index.refresh(&project_dir, blacklist, backend.as_ref())? {
+            index.save(&project_dir, backend.name())?;
+        }
 
-        res
+        let res = index.tags().tags();
+
+        debug!("serving {} tags from the index", res.0.len());
+
+        Ok(res)
     }
 
+    /// Only the tags of definitions, without the pseudo tags ctags describes itself with or
+    /// entries of a `_type` this version doesn't know about.
     pub fn tags(self) -> Self {
         let input_len = self.0.len();
+        let ptags = self.0.iter().filter(|t| t.is_ptag()).count();
 
         let res = Self(self.0.into_iter().filter(Ctag::is_tag).collect());
 
-        trace!("removed {} tags", input_len - res.0.len());
+        trace!("removed {} tags, {ptags} of them pseudo tags", input_len - res.0.len());
 
         res
     }
 
-    pub fn ptags(self) -> Self {
-        Self(self.0.into_iter().filter(Ctag::is_ptag).collect())
-    }
-
     pub fn max_slice(mut self, bpe: &CoreBPE, max_tokens: usize) -> (Self, Self) {
         let mut total_len = 0;
         let mut taken = vec![];
@@ -205,38 +172,7 @@ impl CtagsOutput {
         (max, self)
     }
 
-    pub fn max_slices(mut self, bpe: &CoreBPE, max_tokens: usize) -> Vec<Self> {
-        let pb = ProgressBar::new(self.0.len() as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template(
-                    "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
-                )
-                .unwrap()
-                .progress_chars("#>-"),
-        );
-
-        let mut slices = vec![];
-
-        while !self.is_empty() {
-            let (max, rest) = self.max_slice(bpe, max_tokens);
-            pb.inc(max.0.len() as u64);
-            slices.push(max);
-            self = rest;
-        }
-
-        pb.finish_with_message("done");
-
-        debug!("made {} slices", slices.len());
-
-        slices
-    }
-
-    fn is_empty(&self) -> bool {
-        self.0.len() == 0
-    }
-
-    fn is_not_empty(&self) -> bool {
-        self.0.len() != 0
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 }
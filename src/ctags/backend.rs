@@ -0,0 +1,280 @@
+use crate::ctags::{Ctag, CtagsError};
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+use serde_json::from_str;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use super::treesitter::TreeSitter;
+
+/// Produces [`Ctag`] records for a set of files.
+pub trait TagBackend {
+    /// Short identifier, also used to keep the indexes of different backends apart.
+    fn name(&self) -> &'static str;
+
+    fn tag_files(&self, files: &[PathBuf]) -> anyhow::Result<Vec<Ctag>>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TagBackendKind {
+    Ctags,
+    TreeSitter,
+}
+
+/// Picks the configured backend, or universal ctags when it is installed and tree-sitter otherwise.
+pub fn backend(kind: Option<TagBackendKind>) -> Box<dyn TagBackend> {
+    match kind {
+        Some(TagBackendKind::Ctags) => Box::new(UniversalCtags),
+        Some(TagBackendKind::TreeSitter) => Box::new(TreeSitter),
+        None if UniversalCtags::is_available() => Box::new(UniversalCtags),
+        None => {
+            debug!("ctags is not installed, falling back to tree-sitter");
+            Box::new(TreeSitter)
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UniversalCtags;
+
+lazy_static! {
+    /// Checking runs `ctags --version`, so it is only done once.
+    static ref CTAGS_CHECK: Result<(), CtagsError> = UniversalCtags::probe();
+}
+
+impl UniversalCtags {
+    pub fn is_available() -> bool {
+        CTAGS_CHECK.is_ok()
+    }
+
+    /// Makes sure the installed ctags is universal ctags built with JSON output support.
+    pub fn check() -> Result<(), CtagsError> {
+        match &*CTAGS_CHECK {
+            Ok(()) => Ok(()),
+            Err(CtagsError::NotInstalled) => Err(CtagsError::NotInstalled),
+            Err(CtagsError::UnsupportedVersion { found, reason }) => {
+                Err(CtagsError::UnsupportedVersion { found: found.clone(), reason })
+            }
+            Err(e) => Err(CtagsError::Io(std::io::Error::other(e.to_string()))),
+        }
+    }
+
+    fn probe() -> Result<(), CtagsError> {
+        let res = Self::command().arg("--version").output().map_err(spawn_error)?;
+        let version = String::from_utf8_lossy(&res.stdout);
+        let found = version.lines().next().unwrap_or_default().to_string();
+
+        if !res.status.success() || !version.starts_with("Universal Ctags") {
+            return Err(CtagsError::UnsupportedVersion {
+                found,
+                reason: "only universal ctags is supported",
+            });
+        }
+
+        if !version.contains("+json") {
+            return Err(CtagsError::UnsupportedVersion {
+                found,
+                reason: "it was built without json output support",
+            });
+        }
+
+        Ok(())
+    }
+
+    fn command() -> Command {
+        #[cfg(target_family = "windows")]
+        let proc = Command::new("ctags\\ctags.exe");
+        #[cfg(target_family = "unix")]
+        let proc = Command::new("ctags");
+        proc
+    }
+}
+
+impl TagBackend for UniversalCtags {
+    fn name(&self) -> &'static str {
+        "ctags"
+    }
+
+    fn tag_files(&self, files: &[PathBuf]) -> anyhow::Result<Vec<Ctag>> {
+        Ok(self.run(files)?)
+    }
+}
+
+impl UniversalCtags {
+    /// Runs ctags over the given files and parses its JSON output.
+    pub fn run(&self, files: &[PathBuf]) -> Result<Vec<Ctag>, CtagsError> {
+        Self::check()?;
+
+        let mut child = Self::command()
+            .args([
+                "--languages=Rust,C,C++,C#,Java,JavaScript,Python,Ruby,Go,Kotlin,TypeScript,Elixir,Erlang,Haskell,Lua,Perl,PHP,PowerShell,SQL,Sh,Tcl,Asm,D,Fortran,Cobol,HTML,CSS,JavaProperties",
+                "--kinddef-C=d,devgpt,devgpt-comments",
+                "--regex-C=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-C++=d,devgpt,devgpt-comments",
+                "--regex-C++=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-C#=d,devgpt,devgpt-comments",
+                "--regex-C#=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Java=d,devgpt,devgpt-comments",
+                "--regex-Java=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-JavaScript=d,devgpt,devgpt-comments",
+                "--regex-JavaScript=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Python=d,devgpt,devgpt-comments",
+                "--regex-Python=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Ruby=d,devgpt,devgpt-comments",
+                "--regex-Ruby=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Go=d,devgpt,devgpt-comments",
+                "--regex-Go=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Rust=d,devgpt,devgpt-comments",
+                "--regex-Rust=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Kotlin=d,devgpt,devgpt-comments",
+                "--regex-Kotlin=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-TypeScript=d,devgpt,devgpt-comments",
+                "--regex-TypeScript=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Elixir=d,devgpt,devgpt-comments",
+                "--regex-Elixir=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Erlang=d,devgpt,devgpt-comments",
+                "--regex-Erlang=/%\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Haskell=d,devgpt,devgpt-comments",
+                "--regex-Haskell=/--\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Lua=d,devgpt,devgpt-comments",
+                "--regex-Lua=/--\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Perl=d,devgpt,devgpt-comments",
+                "--regex-Perl=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-PHP=d,devgpt,devgpt-comments",
+                "--regex-PHP=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-PowerShell=d,devgpt,devgpt-comments",
+                "--regex-PowerShell=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-SQL=d,devgpt,devgpt-comments",
+                "--regex-SQL=/--\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Sh=d,devgpt,devgpt-comments",
+                "--regex-Sh=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Tcl=d,devgpt,devgpt-comments",
+                "--regex-Tcl=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Asm=d,devgpt,devgpt-comments",
+                "--regex-Asm=/;\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-D=d,devgpt,devgpt-comments",
+                "--regex-D=/\\/\\/\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Fortran=d,devgpt,devgpt-comments",
+                "--regex-Fortran=/!\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-Cobol=d,devgpt,devgpt-comments",
+                "--regex-Cobol=/\\*\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--kinddef-HTML=d,devgpt,devgpt-comments",
+                "--regex-HTML=/<!--\\s*DEV:\\s*(.*?)\\s*-->/\\1/d/",
+                "--kinddef-CSS=d,devgpt,devgpt-comments",
+                "--regex-CSS=/\\*\\s*DEV:\\s*(.*?)\\s*\\*\\//\\1/d/",
+                "--kinddef-JavaProperties=d,devgpt,devgpt-comments",
+                "--regex-JavaProperties=/#\\s*DEV:\\s*([^\\n]*)/\\1/d/",
+                "--fields=+naeESt",
+                "--output-format=json",
+                "-L",
+                "-",
+                "-f",
+                "-",
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(spawn_error)?;
+
+        // ctags writes tags while it still reads the list, so the list is fed from another thread
+        // while the output is read here, or both sides would block once a pipe is full
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let list = files.iter().map(|file| format!("{}\n", file.display())).collect::<String>();
+        let writer = thread::spawn(move || stdin.write_all(list.as_bytes()));
+
+        let res = child.wait_with_output()?;
+        let written = writer.join().expect("the ctags list writer does not panic");
+
+        trace!("ctags done executing over {} files", files.len());
+
+        if !res.status.success() {
+            return Err(CtagsError::Failed {
+                status: res.status,
+                stderr: String::from_utf8_lossy(&res.stderr).trim().to_string(),
+            });
+        }
+        written?;
+
+        let tags = parse_output(&String::from_utf8(res.stdout)?)?;
+
+        debug!("generated {} tags", tags.len());
+
+        Ok(tags)
+    }
+}
+
+fn spawn_error(e: std::io::Error) -> CtagsError {
+    match e.kind() {
+        ErrorKind::NotFound => CtagsError::NotInstalled,
+        _ => CtagsError::Io(e),
+    }
+}
+
+/// Parses the JSON lines ctags writes, blank lines are skipped.
+fn parse_output(output: &str) -> Result<Vec<Ctag>, CtagsError> {
+    output
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| {
+            from_str::<Ctag>(l.trim()).map_err(|source| CtagsError::MalformedLine {
+                line: i + 1,
+                content: l.to_string(),
+                source,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_output;
+    use crate::ctags::CtagsError;
+
+    #[test]
+    fn parses_output_and_keeps_unknown_types() {
+        let tags = parse_output(concat!(
+            r#"{"_type": "tag", "name": "main", "path": "src/main.rs", "kind": "function", "line": 3}"#,
+            "\n",
+            r#"{"_type": "ptag", "name": "JSON_OUTPUT_VERSION", "path": "0.0"}"#,
+            "\n",
+            r#"{"_type": "future", "name": "something"}"#,
+        ))
+        .unwrap();
+
+        assert_eq!(tags.len(), 3);
+        assert!(tags[0].is_tag());
+        assert!(tags[1].is_ptag());
+        assert!(!tags[2].is_tag() && !tags[2].is_ptag());
+    }
+
+    #[test]
+    fn parses_the_extra_fields() {
+        let tags = parse_output(concat!(
+            r#"{"_type": "tag", "name": "load", "path": "src/config.rs", "kind": "method", "line": 12, "#,
+            r#""end": 20, "signature": "(path: &Path)", "access": "public", "typeref": "typename:Config", "#,
+            r#""extras": "fileScope", "scope": "Config", "scopeKind": "implementation"}"#,
+        ))
+        .unwrap();
+
+        let tag = &tags[0];
+        assert_eq!(tag.end, Some(20));
+        assert_eq!(tag.signature.as_deref(), Some("(path: &Path)"));
+        assert_eq!(tag.access.as_deref(), Some("public"));
+        assert_eq!(tag.typeref.as_deref(), Some("typename:Config"));
+        assert_eq!(tag.extras.as_deref(), Some("fileScope"));
+        assert_eq!(tag.scope_kind.as_deref(), Some("implementation"));
+    }
+
+    #[test]
+    fn reports_malformed_line_numbers() {
+        let err = parse_output("{\"_type\": \"tag\"}\nnot json\n").unwrap_err();
+
+        assert!(matches!(err, CtagsError::MalformedLine { line: 2, .. }));
+    }
+}
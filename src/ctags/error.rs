@@ -0,0 +1,28 @@
+use std::process::ExitStatus;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CtagsError {
+    #[error("ctags is not installed or not on the PATH, install universal ctags or use the tree-sitter backend")]
+    NotInstalled,
+
+    #[error("unsupported ctags ({found}), {reason}")]
+    UnsupportedVersion { found: String, reason: &'static str },
+
+    #[error("ctags exited with {status}: {stderr}")]
+    Failed { status: ExitStatus, stderr: String },
+
+    #[error("ctags output is not valid utf-8: {0}")]
+    InvalidUtf8(#[from] std::string::FromUtf8Error),
+
+    #[error("malformed ctags output on line {line}: {source}, line was `{content}`")]
+    MalformedLine {
+        line: usize,
+        content: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("could not run ctags: {0}")]
+    Io(#[from] std::io::Error),
+}
@@ -0,0 +1,272 @@
+use crate::ctags::backend::TagBackend;
+use crate::ctags::{Ctag, CtagsOutput, TagKind};
+use fnv::FnvHasher;
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::{DirEntry, WalkDir};
+
+pub const INDEX_DIR: &str = ".devgpt";
+/// Bump whenever the layout of [`TagIndex`] or [`Ctag`], or how kinds are classified, changes,
+/// older indexes are then rebuilt.
+const INDEX_VERSION: u32 = 4;
+
+const VCS_DIRS: &[&str] = &[".git", ".hg", ".svn", ".bzr"];
+
+/// On-disk cache of the tags of a project, keyed by file path.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TagIndex {
+    version: u32,
+    files: BTreeMap<PathBuf, IndexedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct IndexedFile {
+    mtime: SystemTime,
+    len: u64,
+    hash: u64,
+    tags: Vec<Ctag>,
+}
+
+impl Default for TagIndex {
+    fn default() -> Self {
+        Self {
+            version: INDEX_VERSION,
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+impl TagIndex {
+    /// Every backend keeps its own index so they can be compared on the same project.
+    pub fn path(project_dir: &Path, backend: &str) -> PathBuf {
+        project_dir.join(INDEX_DIR).join(format!("tags-{backend}.json"))
+    }
+
+    /// Opens the index of the project, an index that is missing, unreadable or from an older
+    /// version is replaced by an empty one.
+    pub fn open(project_dir: &Path, backend: &str) -> anyhow::Result<Self> {
+        let path = Self::path(project_dir, backend);
+        if !path.exists() {
+            debug!("no tag index found at {}", path.display());
+            return Ok(Self::default());
+        }
+
+        let index = serde_json::from_reader::<_, Self>(BufReader::new(File::open(&path)?));
+        match index {
+            Ok(index) if index.version == INDEX_VERSION => Ok(index),
+            Ok(index) => {
+                debug!("tag index has version {}, rebuilding", index.version);
+                Ok(Self::default())
+            }
+            Err(e) => {
+                debug!("tag index could not be read, rebuilding: {e}");
+                Ok(Self::default())
+            }
+        }
+    }
+
+    pub fn save(&self, project_dir: &Path, backend: &str) -> anyhow::Result<()> {
+        let path = Self::path(project_dir, backend);
+        fs::create_dir_all(path.parent().expect("index path has a parent"))?;
+        serde_json::to_writer(BufWriter::new(File::create(&path)?), self)?;
+        trace!("saved tag index to {}", path.display());
+        Ok(())
+    }
+
+    /// Brings the index up to date with the files in `project_dir`. Files whose mtime, size or
+    /// content hash changed are tagged again, entries of deleted files are dropped. Returns whether
+    /// anything changed, an index that didn't needn't be saved again.
+    pub fn refresh(&mut self, project_dir: &Path, blacklist: &[&Path], backend: &dyn TagBackend) -> anyhow::Result<bool> {
+        let mut seen = HashSet::new();
+        let mut stale = BTreeMap::new();
+        let mut touched = 0;
+
+        let walker = WalkDir::new(project_dir)
+            .into_iter()
+            .filter_entry(|e| !is_excluded(e, project_dir, blacklist));
+
+        for entry in walker {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let path = entry.into_path();
+            let meta = fs::metadata(&path)?;
+            let (mtime, len) = (meta.modified()?, meta.len());
+            seen.insert(path.clone());
+
+            match self.files.get_mut(&path) {
+                Some(file) if file.mtime == mtime && file.len == len => {}
+                Some(file) => {
+                    let hash = hash_file(&path)?;
+                    if file.hash == hash {
+                        file.mtime = mtime;
+                        file.len = len;
+                        touched += 1;
+                    } else {
+                        stale.insert(path, IndexedFile { mtime, len, hash, tags: vec![] });
+                    }
+                }
+                None => {
+                    let hash = hash_file(&path)?;
+                    stale.insert(path, IndexedFile { mtime, len, hash, tags: vec![] });
+                }
+            }
+        }
+
+        let before = self.files.len();
+        self.files.retain(|path, _| seen.contains(path));
+        let removed = before - self.files.len();
+
+        if !stale.is_empty() {
+            let paths = stale.keys().cloned().collect::<Vec<_>>();
+            for mut tag in backend.tag_files(&paths)? {
+                tag.canonical_kind = tag.kind.as_deref().map(|kind| TagKind::of(kind, tag.path.as_deref()));
+                let Some(file) = tag.path.as_ref().and_then(|p| stale.get_mut(p)) else {
+                    continue;
+                };
+                file.tags.push(tag);
+            }
+        }
+
+        debug!("tag index: {} files re-tagged, {} removed, {} total", stale.len(), removed, seen.len());
+
+        let changed = !stale.is_empty() || removed > 0 || touched > 0;
+        self.files.extend(stale);
+
+        Ok(changed)
+    }
+
+    pub fn tags(&self) -> CtagsOutput {
+        CtagsOutput(self.files.values().flat_map(|f| f.tags.iter().cloned()).collect())
+    }
+}
+
+fn is_excluded(entry: &DirEntry, project_dir: &Path, blacklist: &[&Path]) -> bool {
+    if entry.depth() == 0 {
+        return false;
+    }
+
+    let relative = entry.path().strip_prefix(project_dir).unwrap_or(entry.path());
+    excludes(relative, entry.file_name(), blacklist)
+}
+
+/// Whether `relative`, a path inside the project, is left out of the index, either itself or
+/// because one of its parent directories is.
+pub fn is_excluded_path(relative: &Path, blacklist: &[&Path]) -> bool {
+    relative
+        .ancestors()
+        .any(|dir| dir.file_name().is_some_and(|name| excludes(dir, name, blacklist)))
+}
+
+/// Blacklist entries are paths relative to the project, `build` leaves out the `build` directory at
+/// the root and not `src/build`.
+fn excludes(relative: &Path, name: &OsStr, blacklist: &[&Path]) -> bool {
+    if name == INDEX_DIR || VCS_DIRS.iter().any(|d| name == *d) {
+        return true;
+    }
+
+    blacklist.contains(&relative)
+}
+
+fn hash_file(path: &Path) -> anyhow::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = FnvHasher::default();
+    let mut buf = [0; 8192];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.write(&buf[..n]);
+    }
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_excluded_path, TagIndex};
+    use crate::ctags::backend::TagBackend;
+    use crate::ctags::Ctag;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    #[derive(Default)]
+    struct FakeBackend {
+        tagged: RefCell<Vec<PathBuf>>,
+    }
+
+    impl TagBackend for FakeBackend {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn tag_files(&self, files: &[PathBuf]) -> anyhow::Result<Vec<Ctag>> {
+            self.tagged.borrow_mut().extend_from_slice(files);
+            Ok(files
+                .iter()
+                .map(|f| Ctag {
+                    _type: "tag".to_string(),
+                    name: Some(f.file_stem().unwrap().to_string_lossy().into_owned()),
+                    path: Some(f.clone()),
+                    kind: Some("function".to_string()),
+                    line: Some(1),
+                    ..Default::default()
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn blacklist_entries_are_relative_to_the_project() {
+        let blacklist = [Path::new("build"), Path::new("web/dist")];
+
+        assert!(is_excluded_path(Path::new("build/out.rs"), &blacklist));
+        assert!(is_excluded_path(Path::new("web/dist/app.js"), &blacklist));
+        assert!(is_excluded_path(Path::new("src/.git/config"), &blacklist));
+        assert!(!is_excluded_path(Path::new("src/build/rules.rs"), &blacklist));
+        assert!(!is_excluded_path(Path::new("dist/app.js"), &blacklist));
+    }
+
+    #[test]
+    fn refresh_only_retags_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path();
+        fs::create_dir(project.join("src")).unwrap();
+        fs::create_dir(project.join("target")).unwrap();
+        fs::write(project.join("src/a.rs"), "fn a() {}").unwrap();
+        fs::write(project.join("src/b.rs"), "fn b() {}").unwrap();
+        fs::write(project.join("target/c.rs"), "fn c() {}").unwrap();
+
+        let backend = FakeBackend::default();
+        let blacklist = [Path::new("target")];
+
+        let mut index = TagIndex::open(project, backend.name()).unwrap();
+        assert!(index.refresh(project, &blacklist, &backend).unwrap());
+        index.save(project, backend.name()).unwrap();
+        assert_eq!(backend.tagged.borrow().len(), 2);
+        assert_eq!(index.tags().0.len(), 2);
+
+        let mut unchanged = TagIndex::open(project, backend.name()).unwrap();
+        assert!(!unchanged.refresh(project, &blacklist, &backend).unwrap());
+
+        backend.tagged.borrow_mut().clear();
+        fs::write(project.join("src/b.rs"), "fn b() { changed() }").unwrap();
+        fs::remove_file(project.join("src/a.rs")).unwrap();
+
+        let mut index = TagIndex::open(project, backend.name()).unwrap();
+        assert!(index.refresh(project, &blacklist, &backend).unwrap());
+        assert_eq!(*backend.tagged.borrow(), vec![project.join("src/b.rs")]);
+        assert_eq!(index.tags().0.len(), 1);
+        assert_eq!(index.tags().0[0].name.as_deref(), Some("b"));
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+/// The kind of a tag the same way for every language, the raw kinds of ctags differ per parser,
+/// a Go `func` is a Rust `function` and a Python `member` is a method.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum TagKind {
+    Function,
+    Method,
+    Type,
+    /// Traits, interfaces and protocols.
+    Trait,
+    Constant,
+    Variable,
+    Module,
+    Field,
+    Macro,
+    DevgptComment,
+    Other,
+}
+
+impl TagKind {
+    /// Classifies a raw ctags kind, `path` tells the languages apart that use a kind differently.
+    pub fn of(raw: &str, path: Option<&Path>) -> Self {
+        let python = path.and_then(Path::extension).is_some_and(|ext| ext == "py" || ext == "pyi");
+        match raw.to_lowercase().as_str() {
+            "function" | "func" | "procedure" | "subroutine" | "generator" | "prototype" => TagKind::Function,
+            "member" if python => TagKind::Method,
+            "method" | "methodspec" | "singletonmethod" | "constructor" => TagKind::Method,
+            // an `impl` block is not a type of its own, it would be a second hit next to its struct
+            "struct" | "class" | "enum" | "union" | "typedef" | "type" | "talias" | "alias" | "record"
+            | "typealias" => TagKind::Type,
+            "interface" | "trait" | "protocol" => TagKind::Trait,
+            "constant" | "const" | "enumerator" | "enumconstant" => TagKind::Constant,
+            "variable" | "var" | "local" | "global" | "externvar" => TagKind::Variable,
+            "module" | "namespace" | "package" | "mod" => TagKind::Module,
+            "field" | "member" | "property" | "attribute" => TagKind::Field,
+            "macro" | "define" => TagKind::Macro,
+            "devgpt" => TagKind::DevgptComment,
+            _ => TagKind::Other,
+        }
+    }
+
+    /// The kind the finder means by `query`, like `functions`, `fn`, `class` or `interface`.
+    pub fn parse(query: &str) -> Option<Self> {
+        let query = query.trim().to_lowercase().replace(['_', ' '], "-");
+        Self::singular(&query)
+            .or_else(|| query.strip_suffix("es").and_then(Self::singular))
+            .or_else(|| query.strip_suffix('s').and_then(Self::singular))
+    }
+
+    fn singular(query: &str) -> Option<Self> {
+        let kind = match query {
+            "function" | "fn" | "func" => TagKind::Function,
+            "method" => TagKind::Method,
+            "type" | "struct" | "class" | "enum" => TagKind::Type,
+            "trait" | "interface" | "protocol" => TagKind::Trait,
+            "constant" | "const" => TagKind::Constant,
+            "variable" | "var" => TagKind::Variable,
+            "module" | "mod" | "namespace" | "package" => TagKind::Module,
+            "field" | "property" | "member" => TagKind::Field,
+            "macro" => TagKind::Macro,
+            "devgpt-comment" | "devgpt" | "dev-comment" => TagKind::DevgptComment,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::ctags::kind::TagKind;
+
+    #[test]
+    fn maps_the_kinds_of_every_language() {
+        assert_eq!(TagKind::of("func", Some(Path::new("main.go"))), TagKind::Function);
+        assert_eq!(TagKind::of("function", Some(Path::new("lib.rs"))), TagKind::Function);
+        assert_eq!(TagKind::of("member", Some(Path::new("app.py"))), TagKind::Method);
+        assert_eq!(TagKind::of("member", Some(Path::new("server.go"))), TagKind::Field);
+        assert_eq!(TagKind::of("methodSpec", None), TagKind::Method);
+        assert_eq!(TagKind::of("interface", None), TagKind::Trait);
+        assert_eq!(TagKind::of("devgpt", None), TagKind::DevgptComment);
+        assert_eq!(TagKind::of("heading", None), TagKind::Other);
+        assert_eq!(TagKind::of("implementation", Some(Path::new("lib.rs"))), TagKind::Other);
+    }
+
+    #[test]
+    fn understands_what_the_finder_asks_for() {
+        assert_eq!(TagKind::parse("functions"), Some(TagKind::Function));
+        assert_eq!(TagKind::parse("Classes"), Some(TagKind::Type));
+        assert_eq!(TagKind::parse("devgpt comments"), Some(TagKind::DevgptComment));
+        assert_eq!(TagKind::parse("implementation"), None);
+    }
+}
@@ -0,0 +1,132 @@
+use std::cmp::Ordering;
+
+/// Names scoring below this don't match, it keeps loose subsequences like `pc` in `post_office` out.
+const MIN_SCORE: f64 = 0.2;
+
+/// Matches symbol names against what the finder asks for, whatever the case or separators, so
+/// `parseConfig`, `parse_config` and `parse-config` are the same name, and `ConfigParser` is close.
+#[derive(Debug, Clone)]
+pub struct NameMatcher {
+    words: Vec<String>,
+    joined: String,
+}
+
+impl NameMatcher {
+    pub fn new(query: &str) -> Self {
+        let words = words(query);
+        Self { joined: words.concat(), words }
+    }
+
+    /// How close `name` is, from 1 for the same name to [`MIN_SCORE`], `None` when it doesn't
+    /// match. The same name beats a name containing it, which beats the same words in another
+    /// order, which beats the letters in order with gaps.
+    pub fn score(&self, name: &str) -> Option<f64> {
+        if self.joined.is_empty() {
+            return None;
+        }
+        let name_words = words(name);
+        let joined = name_words.concat();
+        let coverage = self.joined.len() as f64 / joined.len().max(1) as f64;
+
+        let score = if joined == self.joined {
+            1.0
+        } else if joined.contains(&self.joined) {
+            0.7 + 0.2 * coverage
+        } else if self.words_start(&name_words) {
+            0.5 + 0.2 * coverage
+        } else {
+            let span = subsequence_span(&self.joined, &joined)?;
+            0.5 * self.joined.len() as f64 / span as f64
+        };
+        Some(score).filter(|score| *score >= MIN_SCORE)
+    }
+
+    /// Whether every word of the query starts a different word of the name, in any order.
+    fn words_start(&self, name_words: &[String]) -> bool {
+        let mut used = vec![false; name_words.len()];
+        self.words.iter().all(|word| {
+            let found = name_words.iter().enumerate().find(|(i, name)| !used[*i] && name.starts_with(word.as_str()));
+            found.map(|(i, _)| used[i] = true).is_some()
+        })
+    }
+}
+
+/// Orders scores from best to worst.
+pub fn best_first(a: f64, b: f64) -> Ordering {
+    b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+}
+
+/// The lowercase words of a name in camel, pascal, snake, kebab or any other case, `HTTPServer`
+/// is `http` and `server`.
+fn words(name: &str) -> Vec<String> {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut words = vec![];
+    let mut word = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            words.push(std::mem::take(&mut word));
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(i + 1);
+        let starts_word = c.is_uppercase()
+            && prev.is_some_and(|p| p.is_lowercase() || p.is_numeric() || (p.is_uppercase() && next.is_some_and(|n| n.is_lowercase())));
+        if starts_word {
+            words.push(std::mem::take(&mut word));
+        }
+        word.extend(c.to_lowercase());
+    }
+    words.push(word);
+    words.retain(|w| !w.is_empty());
+    words
+}
+
+/// Length of the shortest stretch of `haystack` that has the letters of `needle` in order, found
+/// greedily from every start.
+fn subsequence_span(needle: &str, haystack: &str) -> Option<usize> {
+    let needle = needle.chars().collect::<Vec<_>>();
+    let haystack = haystack.chars().collect::<Vec<_>>();
+    (0..haystack.len())
+        .filter(|start| haystack[*start] == needle[0])
+        .filter_map(|start| {
+            let mut next = 0;
+            for (i, c) in haystack[start..].iter().enumerate() {
+                if *c == needle[next] {
+                    next += 1;
+                    if next == needle.len() {
+                        return Some(i + 1);
+                    }
+                }
+            }
+            None
+        })
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ctags::name::{words, NameMatcher};
+
+    #[test]
+    fn splits_names_in_any_case() {
+        assert_eq!(words("parseConfig"), ["parse", "config"]);
+        assert_eq!(words("parse_config"), ["parse", "config"]);
+        assert_eq!(words("parse-config"), ["parse", "config"]);
+        assert_eq!(words("HTTPServer2Config"), ["http", "server2", "config"]);
+        assert_eq!(words("__init__"), ["init"]);
+    }
+
+    #[test]
+    fn ranks_closer_names_higher() {
+        let matcher = NameMatcher::new("parse_config");
+        let score = |name| matcher.score(name).unwrap_or_default();
+
+        assert_eq!(score("parseConfig"), 1.0);
+        assert!(score("parseConfigFile") < 1.0);
+        assert!(score("parseConfigFile") > score("ConfigParser"));
+        assert!(score("ConfigParser") > score("parse_cli_config"));
+        assert!(score("parse_cli_config") > 0.0);
+        assert_eq!(matcher.score("print"), None);
+        assert_eq!(NameMatcher::new("pc").score("post_office_sync"), None);
+    }
+}
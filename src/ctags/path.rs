@@ -0,0 +1,98 @@
+use std::path::{Component, Path, PathBuf};
+use globset::{GlobBuilder, GlobMatcher};
+
+/// What the finder asks for when it looks for tags by path, relative to the project or absolute.
+/// A glob like `src/**/*.rs` matches paths relative to the project, or file names when it has no
+/// `/`. Anything else matches the path itself, the files of the directories it names like `src/ai`
+/// or `ai`, and paths ending in it like `search.rs`.
+#[derive(Debug, Clone)]
+pub struct PathPattern {
+    project_dir: Option<PathBuf>,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Path(PathBuf),
+    Glob { matcher: GlobMatcher, file_name: bool },
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str, project_dir: Option<&Path>) -> Result<Self, globset::Error> {
+        let relative = normalize(Path::new(pattern), project_dir);
+        let kind = if pattern.contains(['*', '?', '[', '{']) {
+            let glob = relative.to_string_lossy();
+            let matcher = GlobBuilder::new(&glob).literal_separator(true).build()?.compile_matcher();
+            Kind::Glob { matcher, file_name: !glob.contains('/') }
+        } else {
+            Kind::Path(relative)
+        };
+        Ok(Self { project_dir: project_dir.map(Path::to_path_buf), kind })
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let path = normalize(path, self.project_dir.as_deref());
+        match &self.kind {
+            Kind::Path(pattern) if pattern.as_os_str().is_empty() => true,
+            Kind::Path(pattern) => path.ancestors().any(|dir| dir.ends_with(pattern)),
+            Kind::Glob { matcher, file_name: true } => path.file_name().is_some_and(|name| matcher.is_match(name)),
+            Kind::Glob { matcher, file_name: false } => matcher.is_match(&path),
+        }
+    }
+}
+
+/// `path` relative to `project_dir` when it is inside of it, without `.` components and with `..`
+/// taken out along with the directory before it. The file system isn't looked at.
+pub fn normalize(path: &Path, project_dir: Option<&Path>) -> PathBuf {
+    let path = lexical(path);
+    match project_dir.and_then(|dir| path.strip_prefix(lexical(dir)).ok()) {
+        Some(relative) => relative.to_path_buf(),
+        None => path,
+    }
+}
+
+fn lexical(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normal.components().next_back(), Some(Component::Normal(_))) => {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::ctags::path::PathPattern;
+
+    #[test]
+    fn matches_relative_paths_directories_and_globs() {
+        let project = Path::new("/work/devgpt");
+        let search = project.join("src/ai/search.rs");
+        let matches = |pattern: &str| PathPattern::new(pattern, Some(project)).unwrap().matches(&search);
+
+        assert!(matches("/work/devgpt/src/ai/search.rs"));
+        assert!(matches("./src/ai/search.rs"));
+        assert!(matches("src/../src/ai/search.rs"));
+        assert!(matches("/work/devgpt/./src/ai/../ai/search.rs"));
+        assert!(matches("src/ai"));
+        assert!(matches("src/ai/"));
+        assert!(matches("search.rs"));
+        assert!(matches("ai/search.rs"));
+        assert!(matches("ai"));
+        assert!(matches("src/**/*.rs"));
+        assert!(matches("*.rs"));
+        assert!(matches("src/ai/*.{rs,toml}"));
+
+        assert!(!matches("src/a"));
+        assert!(!matches("earch.rs"));
+        assert!(!matches("src/*.rs"));
+        assert!(!matches("*.md"));
+        assert!(PathPattern::new("src/[", Some(project)).is_err());
+    }
+}
@@ -0,0 +1,33 @@
+(package_clause (package_identifier) @name) @package
+(function_declaration name: (identifier) @name) @func
+(method_declaration
+  receiver: (parameter_list
+    (parameter_declaration
+      type: [
+        (type_identifier) @scope
+        (pointer_type (type_identifier) @scope)
+        (generic_type type: (type_identifier) @scope)
+        (pointer_type (generic_type type: (type_identifier) @scope))
+      ]))
+  name: (field_identifier) @name) @func
+(type_spec name: (type_identifier) @name type: (struct_type)) @struct
+(type_spec name: (type_identifier) @name type: (interface_type)) @interface
+(type_spec
+  name: (type_identifier) @name
+  type: [
+    (type_identifier)
+    (qualified_type)
+    (generic_type)
+    (pointer_type)
+    (array_type)
+    (slice_type)
+    (map_type)
+    (channel_type)
+    (function_type)
+  ]) @type
+(type_alias name: (type_identifier) @name) @talias
+(source_file (const_declaration (const_spec name: (identifier) @name) @const))
+(source_file (var_declaration (var_spec name: (identifier) @name) @var))
+(field_declaration name: (field_identifier) @name) @member
+(method_spec name: (field_identifier) @name) @methodSpec
+(comment) @devgpt
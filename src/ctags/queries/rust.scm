@@ -0,0 +1,20 @@
+(function_item name: (identifier) @name) @function
+(function_signature_item name: (identifier) @name) @function
+(struct_item name: (type_identifier) @name) @struct
+(union_item name: (type_identifier) @name) @struct
+(enum_item name: (type_identifier) @name) @enum
+(enum_variant name: (identifier) @name) @enumerator
+(field_declaration name: (field_identifier) @name) @field
+(trait_item name: (type_identifier) @name) @interface
+(impl_item
+  type: [
+    (type_identifier) @name
+    (generic_type type: (type_identifier) @name)
+    (scoped_type_identifier name: (type_identifier) @name)
+  ]) @implementation
+(mod_item name: (identifier) @name) @module
+(macro_definition name: (identifier) @name) @macro
+(const_item name: (identifier) @name) @constant
+(static_item name: (identifier) @name) @variable
+(type_item name: (type_identifier) @name) @typedef
+(line_comment) @devgpt
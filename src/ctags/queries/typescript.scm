@@ -0,0 +1,19 @@
+(function_declaration name: (identifier) @name) @function
+(function_signature name: (identifier) @name) @function
+(generator_function_declaration name: (identifier) @name) @generator
+(class_declaration name: (type_identifier) @name) @class
+(abstract_class_declaration name: (type_identifier) @name) @class
+(interface_declaration name: (type_identifier) @name) @interface
+(enum_declaration name: (identifier) @name) @enum
+(enum_body name: (property_identifier) @name @enumerator)
+(enum_assignment name: (property_identifier) @name) @enumerator
+(type_alias_declaration name: (type_identifier) @name) @alias
+(method_definition name: (property_identifier) @name) @method
+(method_signature name: (property_identifier) @name) @method
+(abstract_method_signature name: (property_identifier) @name) @method
+(public_field_definition name: (property_identifier) @name) @property
+(property_signature name: (property_identifier) @name) @property
+(internal_module name: (identifier) @name) @namespace
+(program (lexical_declaration (variable_declarator name: (identifier) @name)) @variable)
+(export_statement (lexical_declaration (variable_declarator name: (identifier) @name)) @variable)
+(comment) @devgpt
@@ -0,0 +1,445 @@
+use crate::ctags::backend::TagBackend;
+use crate::ctags::{pattern, Ctag};
+use lazy_static::lazy_static;
+use log::{debug, trace};
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tree_sitter::{Language, Node, Parser, Query, QueryCursor};
+
+lazy_static! {
+    static ref DEVGPT_COMMENT: Regex = Regex::new(r"^(?://|#)\s*DEV:\s*([^\n]*)").unwrap();
+}
+
+/// Built-in tag extractor, it emits the same kinds and scopes ctags does for the languages it
+/// knows and ignores every other file.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TreeSitter;
+
+/// Returns the scope kind and name if the node opens a scope.
+type ScopeFn = fn(Node, &[u8]) -> Option<(&'static str, String)>;
+
+struct Grammar {
+    extensions: &'static [&'static str],
+    language: fn() -> Language,
+    query: &'static str,
+    scope_separator: &'static str,
+    scope: ScopeFn,
+    /// Adjusts the kind of a definition based on the kind of its enclosing scope.
+    refine_kind: fn(&'static str, Option<&str>, Node, &[u8]) -> &'static str,
+    /// Visibility of a definition with the given name, in the words ctags uses.
+    access: fn(Node, &str, &[u8]) -> Option<&'static str>,
+}
+
+const GRAMMARS: &[Grammar] = &[
+    Grammar {
+        extensions: &["rs"],
+        language: tree_sitter_rust::language,
+        query: include_str!("queries/rust.scm"),
+        scope_separator: "::",
+        scope: rust_scope,
+        refine_kind: |kind, scope_kind, _, _| match (kind, scope_kind) {
+            ("function", Some("implementation" | "interface")) => "method",
+            _ => kind,
+        },
+        access: rust_access,
+    },
+    Grammar {
+        extensions: &["py", "pyi"],
+        language: tree_sitter_python::language,
+        query: include_str!("queries/python.scm"),
+        scope_separator: ".",
+        scope: python_scope,
+        refine_kind: |kind, scope_kind, _, _| match (kind, scope_kind) {
+            ("function", Some("class")) => "member",
+            _ => kind,
+        },
+        access: python_access,
+    },
+    Grammar {
+        extensions: &["ts", "mts", "cts"],
+        language: tree_sitter_typescript::language_typescript,
+        query: include_str!("queries/typescript.scm"),
+        scope_separator: ".",
+        scope: typescript_scope,
+        refine_kind: typescript_kind,
+        access: typescript_access,
+    },
+    Grammar {
+        extensions: &["tsx"],
+        language: tree_sitter_typescript::language_tsx,
+        query: include_str!("queries/typescript.scm"),
+        scope_separator: ".",
+        scope: typescript_scope,
+        refine_kind: typescript_kind,
+        access: typescript_access,
+    },
+    Grammar {
+        extensions: &["go"],
+        language: tree_sitter_go::language,
+        query: include_str!("queries/go.scm"),
+        scope_separator: ".",
+        scope: go_scope,
+        refine_kind: |kind, _, _, _| kind,
+        access: go_access,
+    },
+];
+
+impl TagBackend for TreeSitter {
+    fn name(&self) -> &'static str {
+        "tree-sitter"
+    }
+
+    fn tag_files(&self, files: &[PathBuf]) -> anyhow::Result<Vec<Ctag>> {
+        let mut queries: Vec<Option<Query>> = GRAMMARS.iter().map(|_| None).collect();
+        let mut parser = Parser::new();
+        let mut tags = vec![];
+
+        for file in files {
+            let Some(i) = grammar_for(file) else {
+                continue;
+            };
+            let grammar = &GRAMMARS[i];
+
+            let query = match &mut queries[i] {
+                Some(query) => query,
+                slot => slot.insert(Query::new((grammar.language)(), grammar.query)?),
+            };
+
+            let Ok(source) = fs::read_to_string(file) else {
+                debug!("skipping {}, it is not valid utf-8", file.display());
+                continue;
+            };
+
+            parser.set_language((grammar.language)())?;
+            let Some(tree) = parser.parse(&source, None) else {
+                debug!("could not parse {}", file.display());
+                continue;
+            };
+
+            tags.extend(extract(grammar, query, tree.root_node(), &source, file));
+        }
+
+        trace!("tree-sitter done parsing {} files", files.len());
+        debug!("generated {} tags", tags.len());
+
+        Ok(tags)
+    }
+}
+
+fn grammar_for(file: &Path) -> Option<usize> {
+    let extension = file.extension()?.to_str()?;
+    GRAMMARS.iter().position(|g| g.extensions.contains(&extension))
+}
+
+fn extract(grammar: &Grammar, query: &Query, root: Node, source: &str, file: &Path) -> Vec<Ctag> {
+    let bytes = source.as_bytes();
+    let lines = source.lines().collect::<Vec<_>>();
+    let names = query.capture_names();
+    let mut cursor = QueryCursor::new();
+    let mut tags = vec![];
+
+    for m in cursor.matches(query, root, bytes) {
+        let mut name = None;
+        let mut definition = None;
+        let mut scope = None;
+
+        for capture in m.captures {
+            match names[capture.index as usize].as_str() {
+                "name" => name = Some(capture.node),
+                "scope" => scope = Some(capture.node),
+                kind => definition = Some((kind, capture.node)),
+            }
+        }
+
+        let Some((kind, node)) = definition else {
+            continue;
+        };
+        let line = node.start_position().row;
+        let mut tag = Ctag {
+            _type: "tag".to_string(),
+            path: Some(file.to_path_buf()),
+            pattern: lines.get(line).map(|l| pattern(l)),
+            line: Some(line as u32 + 1),
+            ..Default::default()
+        };
+
+        if kind == "devgpt" {
+            let Some(comment) = DEVGPT_COMMENT.captures(text(node, bytes)) else {
+                continue;
+            };
+            tag.name = Some(comment[1].trim_end().to_string());
+            tag.kind = Some("devgpt".to_string());
+            tags.push(tag);
+            continue;
+        }
+
+        let Some(name) = name else {
+            continue;
+        };
+        let name = text(name, bytes);
+        let kind = static_kind(kind);
+        let (scope, scope_kind) = match scope {
+            Some(receiver) => (Some(text(receiver, bytes).to_string()), Some("struct")),
+            None => scope_chain(grammar, node, bytes),
+        };
+        let kind = (grammar.refine_kind)(kind, scope_kind, node, bytes);
+
+        if kind != "implementation" {
+            tag.access = (grammar.access)(node, name, bytes).map(str::to_string);
+        }
+        tag.name = Some(name.to_string());
+        tag.kind = Some(kind.to_string());
+        tag.scope = scope;
+        tag.scope_kind = scope_kind.map(str::to_string);
+        tag.end = Some(node.end_position().row as u32 + 1);
+        tag.signature = node.child_by_field_name("parameters").map(|p| collapse(text(p, bytes)));
+        tag.typeref = typeref(node, bytes);
+        tags.push(tag);
+    }
+
+    tags
+}
+
+/// Capture names live as long as the query, kinds are needed as `&'static str` to refine them.
+fn static_kind(kind: &str) -> &'static str {
+    const KINDS: &[&str] = &[
+        "function", "method", "struct", "enum", "enumerator", "field", "interface", "implementation",
+        "module", "macro", "constant", "variable", "typedef", "class", "member", "generator", "alias",
+        "property", "namespace", "package", "func", "type", "talias", "const", "var", "methodSpec",
+    ];
+    KINDS.iter().find(|k| **k == kind).copied().unwrap_or("unknown")
+}
+
+fn scope_chain(grammar: &Grammar, node: Node, source: &[u8]) -> (Option<String>, Option<&'static str>) {
+    let mut chain = vec![];
+    let mut innermost = None;
+    let mut parent = node.parent();
+
+    while let Some(n) = parent {
+        if let Some((kind, name)) = (grammar.scope)(n, source) {
+            innermost.get_or_insert(kind);
+            chain.push(name);
+        }
+        parent = n.parent();
+    }
+
+    if chain.is_empty() {
+        return (None, None);
+    }
+
+    chain.reverse();
+    (Some(chain.join(grammar.scope_separator)), innermost)
+}
+
+fn rust_scope(node: Node, source: &[u8]) -> Option<(&'static str, String)> {
+    let kind = match node.kind() {
+        "impl_item" => {
+            let ty = text(node.child_by_field_name("type")?, source);
+            let ty = ty.split('<').next().unwrap_or(ty);
+            return Some(("implementation", ty.rsplit("::").next().unwrap_or(ty).to_string()));
+        }
+        "trait_item" => "interface",
+        "mod_item" => "module",
+        "struct_item" | "union_item" => "struct",
+        "enum_item" => "enum",
+        "function_item" => "function",
+        _ => return None,
+    };
+    Some((kind, text(node.child_by_field_name("name")?, source).to_string()))
+}
+
+fn python_scope(node: Node, source: &[u8]) -> Option<(&'static str, String)> {
+    let kind = match node.kind() {
+        "class_definition" => "class",
+        "function_definition" => "function",
+        _ => return None,
+    };
+    Some((kind, text(node.child_by_field_name("name")?, source).to_string()))
+}
+
+fn typescript_scope(node: Node, source: &[u8]) -> Option<(&'static str, String)> {
+    let kind = match node.kind() {
+        "class_declaration" | "abstract_class_declaration" => "class",
+        "interface_declaration" => "interface",
+        "enum_declaration" => "enum",
+        "internal_module" => "namespace",
+        "function_declaration" | "generator_function_declaration" => "function",
+        "method_definition" => "method",
+        _ => return None,
+    };
+    Some((kind, text(node.child_by_field_name("name")?, source).to_string()))
+}
+
+fn typescript_kind(kind: &'static str, _: Option<&str>, node: Node, source: &[u8]) -> &'static str {
+    match node.child_by_field_name("kind") {
+        Some(k) if kind == "variable" && text(k, source) == "const" => "constant",
+        _ => kind,
+    }
+}
+
+fn go_scope(node: Node, source: &[u8]) -> Option<(&'static str, String)> {
+    if node.kind() != "type_spec" {
+        return None;
+    }
+    let kind = match node.child_by_field_name("type")?.kind() {
+        "struct_type" => "struct",
+        "interface_type" => "interface",
+        _ => return None,
+    };
+    Some((kind, text(node.child_by_field_name("name")?, source).to_string()))
+}
+
+/// The return type of a function like ctags puts it, `typename:Config`.
+fn typeref(node: Node, source: &[u8]) -> Option<String> {
+    let ty = node.child_by_field_name("return_type").or_else(|| node.child_by_field_name("result"))?;
+    let ty = text(ty, source).trim_start_matches(':').trim();
+    Some(format!("typename:{}", collapse(ty)))
+}
+
+fn rust_access(node: Node, _: &str, _: &[u8]) -> Option<&'static str> {
+    let mut cursor = node.walk();
+    let public = node.children(&mut cursor).any(|child| child.kind() == "visibility_modifier");
+    Some(if public { "public" } else { "private" })
+}
+
+fn python_access(_: Node, name: &str, _: &[u8]) -> Option<&'static str> {
+    let private = name.starts_with('_') && !(name.starts_with("__") && name.ends_with("__"));
+    Some(if private { "private" } else { "public" })
+}
+
+fn typescript_access(node: Node, _: &str, source: &[u8]) -> Option<&'static str> {
+    let mut cursor = node.walk();
+    let modifier = node.children(&mut cursor).find(|child| child.kind() == "accessibility_modifier")?;
+    ["public", "protected", "private"].into_iter().find(|access| *access == text(modifier, source))
+}
+
+fn go_access(_: Node, name: &str, _: &[u8]) -> Option<&'static str> {
+    Some(if name.starts_with(char::is_uppercase) { "public" } else { "private" })
+}
+
+/// Source text on a single line, signatures can be split over many.
+fn collapse(source: &str) -> String {
+    source.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn text<'a>(node: Node, source: &'a [u8]) -> &'a str {
+    node.utf8_text(source).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TreeSitter;
+    use crate::ctags::backend::TagBackend;
+    use crate::ctags::Ctag;
+    use std::fs;
+
+    fn tag_source(file_name: &str, source: &str) -> Vec<Ctag> {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(file_name);
+        fs::write(&file, source).unwrap();
+        TreeSitter.tag_files(&[file]).unwrap()
+    }
+
+    fn summary(tags: &[Ctag]) -> Vec<(String, String, Option<String>)> {
+        tags.iter()
+            .map(|t| (t.name.clone().unwrap(), t.kind.clone().unwrap(), t.scope.clone()))
+            .collect()
+    }
+
+    fn entry(name: &str, kind: &str, scope: Option<&str>) -> (String, String, Option<String>) {
+        (name.to_string(), kind.to_string(), scope.map(str::to_string))
+    }
+
+    #[test]
+    fn tags_rust() {
+        let tags = tag_source(
+            "lib.rs",
+            "mod config {\n    pub struct Config {\n        dir: String,\n    }\n\n    impl Config {\n        // DEV: open the config\n        pub fn open() {}\n    }\n}\n\nfn main() {}\n",
+        );
+
+        assert_eq!(
+            summary(&tags),
+            vec![
+                entry("config", "module", None),
+                entry("Config", "struct", Some("config")),
+                entry("dir", "field", Some("config::Config")),
+                entry("Config", "implementation", Some("config")),
+                entry("open the config", "devgpt", None),
+                entry("open", "method", Some("config::Config")),
+                entry("main", "function", None),
+            ]
+        );
+        assert_eq!(tags[5].line, Some(8));
+        assert_eq!(tags[5].scope_kind.as_deref(), Some("implementation"));
+        assert_eq!(tags[1].end, Some(4));
+        assert_eq!(tags[1].access.as_deref(), Some("public"));
+        assert_eq!(tags[2].access.as_deref(), Some("private"));
+        assert_eq!(tags[3].access, None);
+        assert_eq!(tags[6].signature.as_deref(), Some("()"));
+        assert_eq!(tags[6].pattern.as_deref(), Some("/^fn main() {}$/"));
+    }
+
+    #[test]
+    fn tags_python() {
+        let tags = tag_source("app.py", "VERSION = 1\n\nclass App:\n    def run(self):\n        pass\n\ndef main():\n    pass\n");
+
+        assert_eq!(
+            summary(&tags),
+            vec![
+                entry("VERSION", "variable", None),
+                entry("App", "class", None),
+                entry("run", "member", Some("App")),
+                entry("main", "function", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn tags_typescript() {
+        let tags = tag_source(
+            "app.ts",
+            "export const LIMIT = 10;\ninterface Shape { area(): number; }\nclass Circle implements Shape {\n  area(): number { return 0; }\n}\n",
+        );
+
+        assert_eq!(
+            summary(&tags),
+            vec![
+                entry("LIMIT", "constant", None),
+                entry("Shape", "interface", None),
+                entry("area", "method", Some("Shape")),
+                entry("Circle", "class", None),
+                entry("area", "method", Some("Circle")),
+            ]
+        );
+        assert_eq!(tags[4].signature.as_deref(), Some("()"));
+        assert_eq!(tags[4].typeref.as_deref(), Some("typename:number"));
+        assert_eq!(tags[4].end, Some(4));
+    }
+
+    #[test]
+    fn tags_go() {
+        let tags = tag_source(
+            "main.go",
+            "package main\n\ntype Server struct {\n\tAddr string\n}\n\nfunc (s *Server) Start() {}\n\nfunc main() {}\n",
+        );
+
+        assert_eq!(
+            summary(&tags),
+            vec![
+                entry("main", "package", None),
+                entry("Server", "struct", None),
+                entry("Addr", "member", Some("Server")),
+                entry("Start", "func", Some("Server")),
+                entry("main", "func", None),
+            ]
+        );
+        assert_eq!(tags[3].access.as_deref(), Some("public"));
+        assert_eq!(tags[4].access.as_deref(), Some("private"));
+    }
+
+    #[test]
+    fn skips_unknown_languages() {
+        assert!(tag_source("notes.txt", "fn main() {}").is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::warn;
+
+/// Ignore files of every directory, later ones win like they do in the blacklist rules.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// The `.gitignore` and `.ignore` files of a project, read as the directories they are in come up,
+/// and `.git/info/exclude`, which applies to the whole tree.
+#[derive(Debug)]
+pub struct GitIgnores {
+    root: PathBuf,
+    exclude: Option<Gitignore>,
+    files: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl GitIgnores {
+    pub fn new(root: &Path) -> Self {
+        let exclude = read(root, &[root.join(".git/info/exclude")]);
+        Self { root: root.to_path_buf(), exclude, files: HashMap::new() }
+    }
+
+    /// Whether `path` is ignored by the ignore files of a directory between it and the root. The
+    /// closest file that has a rule for it wins, like in git, and `info/exclude` comes last.
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        let Some(parent) = path.parent() else {
+            return false;
+        };
+
+        let root = self.root.clone();
+        for dir in parent.ancestors().take_while(|dir| dir.starts_with(&root)) {
+            match self.file(dir).map(|file| file.matched(path, is_dir)) {
+                Some(Match::Ignore(_)) => return true,
+                Some(Match::Whitelist(_)) => return false,
+                _ => {}
+            }
+        }
+        self.exclude.as_ref().is_some_and(|exclude| exclude.matched(path, is_dir).is_ignore())
+    }
+
+    fn file(&mut self, dir: &Path) -> Option<&Gitignore> {
+        self.files
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| read(dir, &IGNORE_FILES.map(|file| dir.join(file))))
+            .as_ref()
+    }
+}
+
+/// The rules of the ignore `files` of `dir` that exist, none when none does.
+fn read(dir: &Path, files: &[PathBuf]) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut any = false;
+    for path in files.iter().filter(|path| path.is_file()) {
+        any = true;
+        if let Some(e) = builder.add(path) {
+            warn!("skipping part of {}: {e}", path.display());
+        }
+    }
+    if !any {
+        return None;
+    }
+    builder.build().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::gitignore::GitIgnores;
+
+    #[test]
+    fn closest_gitignore_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("app/logs")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\nlogs/\n").unwrap();
+        fs::write(root.join("app/.gitignore"), "!keep.log\n").unwrap();
+
+        let mut ignores = GitIgnores::new(root);
+        assert!(ignores.is_ignored(&root.join("debug.log"), false));
+        assert!(ignores.is_ignored(&root.join("app/debug.log"), false));
+        assert!(!ignores.is_ignored(&root.join("app/keep.log"), false));
+        assert!(ignores.is_ignored(&root.join("app/logs"), true));
+        assert!(!ignores.is_ignored(&root.join("app/main.rs"), false));
+    }
+
+    #[test]
+    fn reads_ignore_files_and_the_git_excludes_everywhere() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join(".git/info")).unwrap();
+        fs::create_dir_all(root.join("app/generated")).unwrap();
+        fs::write(root.join(".git/info/exclude"), "scratch/\n").unwrap();
+        fs::write(root.join("app/.ignore"), "generated/\n").unwrap();
+        fs::write(root.join("app/.gitignore"), "!scratch/\n").unwrap();
+
+        let mut ignores = GitIgnores::new(root);
+        assert!(ignores.is_ignored(&root.join("app/generated"), true));
+        assert!(ignores.is_ignored(&root.join("lib/scratch"), true));
+        assert!(!ignores.is_ignored(&root.join("app/scratch"), true));
+        assert!(!ignores.is_ignored(&root.join("app/src"), true));
+    }
+}
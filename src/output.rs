@@ -0,0 +1,217 @@
+use std::io::Write;
+use std::process::ExitCode;
+use clap::ValueEnum;
+use serde_derive::Serialize;
+use crate::ai::budget::StopReason;
+use crate::ai::search::FoundFile;
+use crate::ctags::Ctag;
+
+/// Exit code of a search that found something, also used by every other successful command.
+pub const FOUND: u8 = 0;
+/// Exit code of a search that ran fine but found nothing.
+pub const NOT_FOUND: u8 = 1;
+/// Exit code of any command that failed.
+pub const ERROR: u8 = 2;
+/// Exit code of a search that hit one of its limits before the finder was done, its results are
+/// the best candidates so far.
+pub const TRUNCATED: u8 = 3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One path per line
+    #[default]
+    Paths,
+    /// A JSON object with why the search ended and every result, its lines, tags, snippet,
+    /// confidence and justification
+    Json,
+    /// `path:line:text` for every line of the snippets, like `grep -n`
+    Grep,
+    /// `path:line:column: justification`, read by vim's quickfix list and emacs' compilation mode
+    Quickfix,
+}
+
+/// What the JSON format prints.
+#[derive(Serialize)]
+struct Report<'a> {
+    reason: StopReason,
+    files: &'a [FoundFile],
+}
+
+/// Writes the search results in the given format and returns the exit code for them.
+pub fn write_results(
+    out: &mut impl Write,
+    format: OutputFormat,
+    found: Option<&[FoundFile]>,
+    reason: StopReason,
+) -> anyhow::Result<ExitCode> {
+    let found = found.unwrap_or_default();
+
+    match format {
+        OutputFormat::Paths => {
+            for file in found {
+                writeln!(out, "{}", file.path.display())?;
+            }
+        }
+        OutputFormat::Json => {
+            writeln!(out, "{}", serde_json::to_string_pretty(&Report { reason, files: found })?)?;
+        }
+        OutputFormat::Grep => {
+            for file in found {
+                match (&file.snippet, file.lines) {
+                    (Some(snippet), Some(lines)) => {
+                        for (i, text) in snippet.lines().enumerate() {
+                            writeln!(out, "{}:{}:{}", file.path.display(), lines.start + i as u32, text)?;
+                        }
+                    }
+                    _ => {
+                        for tag in tags(file) {
+                            let text = tag.and_then(pattern_text).unwrap_or_default();
+                            writeln!(out, "{}:{}:{}", file.path.display(), line(tag), text)?;
+                        }
+                    }
+                }
+            }
+        }
+        OutputFormat::Quickfix => {
+            for file in found {
+                match (&file.justification, file.confidence) {
+                    (Some(justification), Some(confidence)) => {
+                        let start = file.lines.map_or(1, |lines| lines.start);
+                        writeln!(out, "{}:{start}:1: {justification} (confidence {confidence:.2})", file.path.display())?;
+                    }
+                    _ => {
+                        for tag in tags(file) {
+                            let message = tag.map(describe).unwrap_or_else(|| "matched file".to_string());
+                            writeln!(out, "{}:{}:1: {}", file.path.display(), line(tag), message)?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(ExitCode::from(match reason {
+        StopReason::Answered if found.is_empty() => NOT_FOUND,
+        StopReason::Answered => FOUND,
+        _ => TRUNCATED,
+    }))
+}
+
+/// Every tag of a result without a snippet or justification, a result without tags is listed
+/// once on its first line.
+fn tags(file: &FoundFile) -> Vec<Option<&Ctag>> {
+    if file.tags.is_empty() {
+        vec![None]
+    } else {
+        file.tags.iter().map(Some).collect()
+    }
+}
+
+fn line(tag: Option<&Ctag>) -> u32 {
+    tag.and_then(|t| t.line).unwrap_or(1)
+}
+
+/// Recovers the source line from a ctags search pattern like `/^fn main() {$/`.
+fn pattern_text(tag: &Ctag) -> Option<String> {
+    let pattern = tag.pattern.as_deref()?;
+    let pattern = pattern.strip_prefix("/^")?.strip_suffix('/')?;
+    let pattern = pattern.strip_suffix('$').unwrap_or(pattern);
+    Some(pattern.replace("\\/", "/").replace("\\\\", "\\"))
+}
+
+fn describe(tag: &Ctag) -> String {
+    let kind = tag.kind.as_deref().unwrap_or("tag");
+    let name = tag.name.as_deref().unwrap_or_default();
+    let name = format!("{name}{}", tag.signature.as_deref().unwrap_or_default());
+    match &tag.scope {
+        Some(scope) => format!("{kind} {name} in {scope}"),
+        None => format!("{kind} {name}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::process::ExitCode;
+    use serde_json::json;
+    use crate::ai::budget::StopReason;
+    use crate::ai::search::FoundFile;
+    use crate::ctags::Ctag;
+    use super::{write_results, OutputFormat, FOUND, NOT_FOUND, TRUNCATED};
+
+    fn found() -> Vec<FoundFile> {
+        let tags = [Ctag {
+            _type: "tag".to_string(),
+            name: Some("main".to_string()),
+            path: Some(PathBuf::from("src/main.rs")),
+            pattern: Some("/^async fn main() -> anyhow::Result<()> {$/".to_string()),
+            kind: Some("function".to_string()),
+            line: Some(19),
+            ..Default::default()
+        }];
+        vec![FoundFile::new(PathBuf::from("src/main.rs"), None, &tags)]
+    }
+
+    fn answered() -> Vec<FoundFile> {
+        let mut found = found();
+        found[0].snippet = Some("async fn main() -> anyhow::Result<()> {\n    cli::run().await\n".to_string());
+        found[0].confidence = Some(0.9);
+        found[0].justification = Some("the entry point of the binary".to_string());
+        found
+    }
+
+    fn render(format: OutputFormat, found: Option<&[FoundFile]>) -> String {
+        let mut out = vec![];
+        write_results(&mut out, format, found, StopReason::Answered).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn renders_line_formats() {
+        let found = found();
+
+        assert_eq!(render(OutputFormat::Paths, Some(&found)), "src/main.rs\n");
+        assert_eq!(render(OutputFormat::Grep, Some(&found)), "src/main.rs:19:async fn main() -> anyhow::Result<()> {\n");
+        assert_eq!(render(OutputFormat::Quickfix, Some(&found)), "src/main.rs:19:1: function main\n");
+    }
+
+    #[test]
+    fn renders_snippets_and_justifications() {
+        let found = answered();
+
+        assert_eq!(
+            render(OutputFormat::Grep, Some(&found)),
+            "src/main.rs:19:async fn main() -> anyhow::Result<()> {\nsrc/main.rs:20:    cli::run().await\n"
+        );
+        assert_eq!(
+            render(OutputFormat::Quickfix, Some(&found)),
+            "src/main.rs:19:1: the entry point of the binary (confidence 0.90)\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(&render(OutputFormat::Json, Some(&found))).unwrap();
+        assert_eq!(json["reason"], "answered");
+        assert_eq!(json["files"][0]["lines"], json!({ "start": 19, "end": 19 }));
+        assert_eq!(json["files"][0]["confidence"], 0.9);
+    }
+
+    #[test]
+    fn nothing_found_has_no_files() {
+        let json: serde_json::Value = serde_json::from_str(&render(OutputFormat::Json, None)).unwrap();
+        assert_eq!(json, json!({ "reason": "answered", "files": [] }));
+    }
+
+    #[test]
+    fn tells_truncated_searches_apart() {
+        let found = found();
+        let mut out = vec![];
+
+        let code = write_results(&mut out, OutputFormat::Json, Some(&found), StopReason::MaxRounds(25)).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+        assert_eq!(code, ExitCode::from(TRUNCATED));
+        assert_eq!(json["reason"], json!({ "max_rounds": 25 }));
+        let code = write_results(&mut vec![], OutputFormat::Paths, Some(&found), StopReason::Answered).unwrap();
+        assert_eq!(code, ExitCode::from(FOUND));
+        let code = write_results(&mut vec![], OutputFormat::Paths, None, StopReason::Answered).unwrap();
+        assert_eq!(code, ExitCode::from(NOT_FOUND));
+    }
+}
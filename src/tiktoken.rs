@@ -1,6 +1,13 @@
-use tiktoken_rs::CoreBPE;
+use tiktoken_rs::{cl100k_base, get_bpe_from_model, CoreBPE};
+use crate::ai::provider::CompletionRequest;
+use crate::ai::wire::Message;
 use crate::ctags::{Ctag, CtagsOutput};
 
+/// The tokenizer of `model`, models tiktoken doesn't know are counted like gpt-4.
+pub fn bpe_for(model: &str) -> CoreBPE {
+    get_bpe_from_model(model).unwrap_or_else(|_| cl100k_base().expect("cl100k_base is built in"))
+}
+
 pub trait TokensLen {
     fn token_len(&self, bpe: &CoreBPE) -> usize;
 }
@@ -23,6 +30,20 @@ impl TokensLen for CtagsOutput {
     }
 }
 
+impl TokensLen for Message {
+    fn token_len(&self, bpe: &CoreBPE) -> usize {
+        count_tokens(&serde_json::to_string(self).unwrap(), bpe)
+    }
+}
+
+/// The prompt side of a request, every message plus the tool definitions.
+impl TokensLen for CompletionRequest {
+    fn token_len(&self, bpe: &CoreBPE) -> usize {
+        let tools = self.tools.as_ref().map_or(0, |t| count_tokens(&serde_json::to_string(t).unwrap(), bpe));
+        self.messages.iter().fold(tools, |acc, m| acc + m.token_len(bpe))
+    }
+}
+
 fn count_tokens(s: &str, bpe: &CoreBPE) -> usize {
     bpe.encode_with_special_tokens(s).len()
 }